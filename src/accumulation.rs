@@ -0,0 +1,204 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+
+use crate::checkpoint::{invalid_data, read_u32, read_u64, write_u32, write_u64};
+use crate::film::Film;
+use crate::filter::Filter;
+use crate::framebuffer::Framebuffer;
+
+const MAGIC: &[u8; 4] = b"RTAC";
+const VERSION: u32 = 2;
+
+// Unnormalised film sums and weights of a render plus the samples taken per
+// pixel. Renders of the same scene with different seeds merge into exactly
+// what one render with their combined sample count would have accumulated.
+// The camera's fingerprint keeps renders of other scenes or with other
+// samplers, filters or spectral settings from being merged in.
+pub struct Accumulation {
+    film: Film,
+    samples: Vec<u32>,
+    fingerprint: u64,
+}
+
+impl Accumulation {
+    pub(crate) fn new(film: Film, samples: Vec<u32>, fingerprint: u64) -> Accumulation {
+        Accumulation {
+            film,
+            samples,
+            fingerprint,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.film.width()
+    }
+
+    pub fn height(&self) -> usize {
+        self.film.height()
+    }
+
+    pub fn samples(&self, x: usize, y: usize) -> u32 {
+        self.samples[y * self.width() + x]
+    }
+
+    pub fn merge(&mut self, other: &Accumulation) {
+        assert!(
+            self.width() == other.width() && self.height() == other.height(),
+            "Merged renders must have the same resolution"
+        );
+        assert!(
+            self.fingerprint == other.fingerprint,
+            "Merged renders must be of the same scene with the same sampler, filter and spectral setting"
+        );
+        self.film
+            .add_tile(&other.film.tile(0, 0, other.width(), other.height()));
+        for (samples, other_samples) in self.samples.iter_mut().zip(&other.samples) {
+            *samples += other_samples;
+        }
+    }
+
+    pub fn to_framebuffer(&self) -> Framebuffer {
+        self.film.to_framebuffer()
+    }
+
+    // Written to a temporary file first like checkpoints
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        let temp_path = format!("{}.tmp", path);
+        let mut out = BufWriter::new(File::create(&temp_path)?);
+        out.write_all(MAGIC)?;
+        write_u32(&mut out, VERSION)?;
+        write_u64(&mut out, self.fingerprint)?;
+        self.film.write(&mut out)?;
+        for samples in &self.samples {
+            write_u32(&mut out, *samples)?;
+        }
+        out.flush()?;
+        drop(out);
+        fs::rename(&temp_path, path)
+    }
+
+    // The filter only matters for adding samples, merging just adds sums
+    pub fn load(path: &str) -> io::Result<Accumulation> {
+        let mut input = BufReader::new(File::open(path)?);
+        let mut magic = [0; 4];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC || read_u32(&mut input)? != VERSION {
+            return Err(invalid_data("not a render accumulation"));
+        }
+        let fingerprint = read_u64(&mut input)?;
+        let film = Film::read(&mut input, Filter::default())?;
+        let samples = (0..film.width() * film.height())
+            .map(|_| read_u32(&mut input))
+            .collect::<io::Result<Vec<u32>>>()?;
+        Ok(Accumulation {
+            film,
+            samples,
+            fingerprint,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::Camera;
+    use crate::colour::Colour;
+    use crate::filter::FilterKind;
+    use crate::hittable::HittableList;
+    use crate::progress::Silent;
+    use crate::scene::{MaterialDesc, Scene};
+    use crate::vec3::Point3;
+
+    fn scene() -> Scene {
+        let mut scene = Scene::new();
+        let grey = MaterialDesc::Lambertian {
+            albedo: Colour::new(0.5, 0.5, 0.5),
+        };
+        scene.add_sphere(Point3::new(0.0, -1000.0, 0.0), 1000.0, grey.clone());
+        scene.add_sphere(Point3::new(0.0, 1.0, 0.0), 1.0, grey);
+        scene
+    }
+
+    fn camera(scene: &Scene) -> Camera {
+        let mut cam = Camera::new();
+        cam.set_image_width(16);
+        cam.set_filter(Filter::new(FilterKind::Gaussian));
+        cam.set_seed(7);
+        cam.set_scene_fingerprint(scene.fingerprint());
+        cam
+    }
+
+    // Renders samples `start..end` of every pixel, so two renders split the
+    // samples of one
+    fn render(cam: &mut Camera, world: &HittableList, start: i32, end: i32) -> Accumulation {
+        let mut state = cam.new_render_state();
+        state.samples_done = start;
+        for pixel in &mut state.pixels {
+            pixel.samples = start;
+        }
+        cam.set_progressive(Some(end - start));
+        assert!(cam.render_pass(world, &[], &mut state, &mut Silent));
+        let accumulation = cam.output(&state, &[]).accumulation;
+        // Counts only the samples this render took, as a render with its own
+        // seed would
+        Accumulation {
+            samples: accumulation
+                .samples
+                .iter()
+                .map(|s| s - start as u32)
+                .collect(),
+            ..accumulation
+        }
+    }
+
+    #[test]
+    fn halves_merge_into_full_render() {
+        let scene = scene();
+        let world = scene.build();
+        let mut cam = camera(&scene);
+        let samples = cam.max_samples();
+        let full = render(&mut cam, &world, 0, samples);
+        let mut merged = render(&mut cam, &world, 0, samples / 2);
+        merged.merge(&render(&mut cam, &world, samples / 2, samples));
+
+        let (image, merged_image) = (full.to_framebuffer(), merged.to_framebuffer());
+        for y in 0..full.height() {
+            for x in 0..full.width() {
+                assert_eq!(merged.samples(x, y), full.samples(x, y));
+                let difference = image.get(x, y) - merged_image.get(x, y);
+                assert!(difference.length() < 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "same scene")]
+    fn different_scenes_do_not_merge() {
+        let scene = scene();
+        let world = scene.build();
+        let mut other = scene.clone();
+        other.add_sphere(
+            Point3::new(2.0, 0.5, 0.0),
+            0.5,
+            MaterialDesc::Lambertian {
+                albedo: Colour::new(0.9, 0.1, 0.1),
+            },
+        );
+
+        let mut cam = camera(&scene);
+        let mut first = render(&mut cam, &world, 0, 4);
+        cam.set_scene_fingerprint(other.fingerprint());
+        first.merge(&render(&mut cam, &other.build(), 0, 4));
+    }
+
+    #[test]
+    #[should_panic(expected = "same scene")]
+    fn different_filters_do_not_merge() {
+        let scene = scene();
+        let world = scene.build();
+        let mut cam = camera(&scene);
+        let mut first = render(&mut cam, &world, 0, 4);
+        cam.set_filter(Filter::new(FilterKind::Tent));
+        first.merge(&render(&mut cam, &world, 0, 4));
+    }
+}
@@ -0,0 +1,138 @@
+use std::io::{self, Read, Write};
+
+use crate::checkpoint::{read_f64, read_u64, write_f64, write_u64};
+use crate::colour::{self, Colour};
+use crate::common;
+
+// Stop sampling a pixel once the relative standard error of its mean
+// luminance drops below the threshold
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct AdaptiveSampling {
+    pub min_samples: i32,
+    pub max_samples: i32,
+    pub threshold: f64,
+}
+
+impl AdaptiveSampling {
+    pub fn new(threshold: f64) -> AdaptiveSampling {
+        AdaptiveSampling {
+            min_samples: 16,
+            max_samples: 256,
+            threshold,
+        }
+    }
+
+    pub(crate) fn converged(&self, variance: &Welford) -> bool {
+        variance.count >= self.min_samples as u64 && variance.relative_error() < self.threshold
+    }
+}
+
+// Welford's online mean and variance of sample luminance
+#[derive(Default)]
+pub(crate) struct Welford {
+    count: u64,
+    mean: f64,
+    m2: f64,
+}
+
+impl Welford {
+    pub fn new() -> Welford {
+        Default::default()
+    }
+
+    pub fn add(&mut self, sample: Colour) {
+        let x = colour::luminance(sample);
+        self.count += 1;
+        let delta = x - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (x - self.mean);
+    }
+
+    pub fn write(&self, out: &mut impl Write) -> io::Result<()> {
+        write_u64(out, self.count)?;
+        write_f64(out, self.mean)?;
+        write_f64(out, self.m2)
+    }
+
+    pub fn read(input: &mut impl Read) -> io::Result<Welford> {
+        Ok(Welford {
+            count: read_u64(input)?,
+            mean: read_f64(input)?,
+            m2: read_f64(input)?,
+        })
+    }
+
+    fn relative_error(&self) -> f64 {
+        if self.count < 2 {
+            return common::INFINITY;
+        }
+        let variance = self.m2 / (self.count - 1) as f64;
+        let standard_error = f64::sqrt(variance / self.count as f64);
+        // Small offset so black pixels can still converge
+        standard_error / (self.mean + 1.0e-3)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::camera::Camera;
+    use crate::hittable::HittableList;
+    use crate::material::Lambertian;
+    use crate::sphere::Sphere;
+    use crate::vec3::Point3;
+
+    #[test]
+    fn welford_matches_two_pass() {
+        common::seed_random(9);
+        let samples: Vec<Colour> = (0..1000)
+            .map(|_| 10.0 * colour::random() * colour::random())
+            .collect();
+        let mut welford = Welford::new();
+        for &sample in &samples {
+            welford.add(sample);
+        }
+
+        let n = samples.len() as f64;
+        let luminance: Vec<f64> = samples.iter().map(|&c| colour::luminance(c)).collect();
+        let mean = luminance.iter().sum::<f64>() / n;
+        let variance = luminance.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0);
+        assert_eq!(welford.count, samples.len() as u64);
+        assert!((welford.mean - mean).abs() < 1e-12);
+        assert!((welford.m2 / (n - 1.0) - variance).abs() < 1e-9);
+    }
+
+    #[test]
+    fn constant_pixel_stops_at_min_samples() {
+        let adaptive = AdaptiveSampling {
+            min_samples: 8,
+            max_samples: 64,
+            threshold: 0.01,
+        };
+        let mut welford = Welford::new();
+        for count in 1..=8 {
+            assert!(!adaptive.converged(&welford));
+            welford.add(Colour::new(0.2, 0.4, 0.6));
+            assert_eq!(adaptive.converged(&welford), count == 8);
+        }
+
+        // Inside a sphere without light every path comes back black
+        let mut world = HittableList::new();
+        world.add(Box::new(Sphere::new(
+            Point3::new(0.0, 0.0, 0.0),
+            100.0,
+            Rc::new(Lambertian::new(Colour::new(0.5, 0.5, 0.5))),
+        )));
+        let mut cam = Camera::new();
+        cam.set_image_width(8);
+        cam.set_adaptive_sampling(Some(adaptive));
+        let output = cam.render(&world, &[]);
+        for y in 0..output.accumulation.height() {
+            for x in 0..output.accumulation.width() {
+                assert_eq!(output.accumulation.samples(x, y), 8);
+            }
+        }
+    }
+}
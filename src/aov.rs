@@ -0,0 +1,218 @@
+use std::io::{self, Read, Write};
+
+use crate::checkpoint::{read_f64, read_u32, read_u64, read_vec3};
+use crate::checkpoint::{write_f64, write_u32, write_u64, write_vec3};
+use crate::colour::Colour;
+use crate::common;
+use crate::framebuffer::Framebuffer;
+use crate::hittable::HitRecord;
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+
+// Auxiliary render passes written alongside the beauty image
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Aov {
+    Albedo,
+    Normal,
+    Depth,
+    ObjectId,
+    SampleCount,
+}
+
+impl Aov {
+    pub const ALL: [Aov; 5] = [
+        Aov::Albedo,
+        Aov::Normal,
+        Aov::Depth,
+        Aov::ObjectId,
+        Aov::SampleCount,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Aov::Albedo => "albedo",
+            Aov::Normal => "normal",
+            Aov::Depth => "depth",
+            Aov::ObjectId => "id",
+            Aov::SampleCount => "samples",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Aov> {
+        Aov::ALL.into_iter().find(|aov| aov.name() == name)
+    }
+}
+
+pub struct AovBuffers {
+    buffers: Vec<(Aov, Framebuffer)>,
+}
+
+impl AovBuffers {
+    pub fn new(aovs: &[Aov], width: usize, height: usize) -> AovBuffers {
+        let mut buffers: Vec<(Aov, Framebuffer)> = Vec::new();
+        for aov in aovs {
+            if buffers.iter().all(|(a, _)| a != aov) {
+                buffers.push((*aov, Framebuffer::new(width, height)));
+            }
+        }
+        AovBuffers { buffers }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffers.is_empty()
+    }
+
+    pub fn get(&self, aov: Aov) -> Option<&Framebuffer> {
+        self.buffers.iter().find(|(a, _)| *a == aov).map(|(_, b)| b)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Aov, &Framebuffer)> {
+        self.buffers.iter().map(|(a, b)| (*a, b))
+    }
+
+    pub(crate) fn store(&mut self, x: usize, y: usize, pixel: &AovPixel) {
+        for (aov, buffer) in &mut self.buffers {
+            buffer.set(x, y, pixel.value(*aov));
+        }
+    }
+}
+
+// Per-pixel accumulator for the first hit of every camera sample.
+// Albedo and normal are averaged, depth and object id come from the nearest hit.
+pub(crate) struct AovPixel {
+    albedo: Colour,
+    normal: Vec3,
+    depth: f64,
+    object_id: Option<usize>,
+    samples: i32,
+}
+
+impl AovPixel {
+    pub fn new() -> AovPixel {
+        AovPixel {
+            albedo: Colour::default(),
+            normal: Vec3::default(),
+            depth: common::INFINITY,
+            object_id: None,
+            samples: 0,
+        }
+    }
+
+    pub fn add(&mut self, r: &Ray, rec: Option<&HitRecord>, background: Colour) {
+        self.samples += 1;
+        match rec {
+            Some(rec) => {
+                self.albedo += rec.mat.as_ref().unwrap().albedo(rec);
+                self.normal += rec.normal;
+                let distance = rec.t * r.direction.length();
+                if distance < self.depth {
+                    self.depth = distance;
+                    self.object_id = Some(rec.object_id);
+                }
+            }
+            None => self.albedo += background,
+        }
+    }
+
+    pub fn write(&self, out: &mut impl Write) -> io::Result<()> {
+        write_vec3(out, self.albedo)?;
+        write_vec3(out, self.normal)?;
+        write_f64(out, self.depth)?;
+        write_u64(out, self.object_id.map_or(u64::MAX, |id| id as u64))?;
+        write_u32(out, self.samples as u32)
+    }
+
+    pub fn read(input: &mut impl Read) -> io::Result<AovPixel> {
+        Ok(AovPixel {
+            albedo: read_vec3(input)?,
+            normal: read_vec3(input)?,
+            depth: read_f64(input)?,
+            object_id: match read_u64(input)? {
+                u64::MAX => None,
+                id => Some(id as usize),
+            },
+            samples: read_u32(input)? as i32,
+        })
+    }
+
+    fn value(&self, aov: Aov) -> Colour {
+        let scale = 1.0 / self.samples.max(1) as f64;
+        match aov {
+            Aov::Albedo => self.albedo * scale,
+            Aov::Normal => self.normal * scale,
+            Aov::Depth => Colour::new(self.depth, self.depth, self.depth),
+            // 0 is reserved for the background
+            Aov::ObjectId => {
+                let id = self.object_id.map_or(0.0, |id| (id + 1) as f64);
+                Colour::new(id, id, id)
+            }
+            // Heatmap of where adaptive sampling spent its samples
+            Aov::SampleCount => {
+                let n = self.samples as f64;
+                Colour::new(n, n, n)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::adaptive::AdaptiveSampling;
+    use crate::camera::Camera;
+    use crate::hittable::HittableList;
+    use crate::material::Lambertian;
+    use crate::sphere::Sphere;
+    use crate::vec3::{unit_vector, Point3};
+
+    #[test]
+    fn aovs_record_the_first_hit() {
+        let centre = Point3::new(0.0, 0.0, -1.0);
+        let albedo = Colour::new(0.3, 0.6, 0.9);
+        let mut world = HittableList::new();
+        world.add(Box::new(Sphere::new(
+            centre,
+            2.0,
+            Rc::new(Lambertian::new(albedo)),
+        )));
+        let mut cam = Camera::new();
+        cam.set_image_width(32);
+        cam.set_adaptive_sampling(Some(AdaptiveSampling {
+            min_samples: 8,
+            max_samples: 32,
+            threshold: 0.05,
+        }));
+        let output = cam.render(&world, &Aov::ALL);
+        let aov = |aov, x, y| output.aovs.get(aov).unwrap().get(x, y);
+
+        // The middle of the image sees the front of the sphere, where the
+        // averaged normal points back to a hit at the recorded depth
+        let (x, y) = (cam.image_width() / 2, cam.image_height() / 2);
+        let normal = aov(Aov::Normal, x, y);
+        let depth = aov(Aov::Depth, x, y).x();
+        let hit = centre + 2.0 * normal;
+        assert!((aov(Aov::Albedo, x, y) - albedo).length() < 1e-12);
+        assert!((normal.length() - 1.0).abs() < 0.01);
+        assert!(normal.dot(&unit_vector(cam.look_from() - centre)) > 0.95);
+        assert!(((cam.look_from() - hit).length() - depth).abs() < 0.05);
+        assert_eq!(aov(Aov::ObjectId, x, y), Colour::new(1.0, 1.0, 1.0));
+
+        // The corners only see the sky, which is always fully blue
+        let corner = aov(Aov::Albedo, 0, 0);
+        assert_eq!(corner.z(), 1.0);
+        assert!(corner.x() < 1.0);
+        assert_eq!(aov(Aov::Normal, 0, 0), Vec3::default());
+        assert_eq!(aov(Aov::Depth, 0, 0).x(), common::INFINITY);
+        assert_eq!(aov(Aov::ObjectId, 0, 0), Colour::default());
+
+        for y in 0..cam.image_height() {
+            for x in 0..cam.image_width() {
+                let n = output.accumulation.samples(x, y) as f64;
+                assert!((8.0..=32.0).contains(&n));
+                assert_eq!(aov(Aov::SampleCount, x, y), Colour::new(n, n, n));
+            }
+        }
+    }
+}
@@ -1,19 +1,41 @@
-use crate::colour;
+use crate::accumulation::Accumulation;
+use crate::adaptive::AdaptiveSampling;
+use std::ops::Range;
+use std::time::Duration;
+
+use crate::aov::{Aov, AovBuffers, AovPixel};
+use crate::cancel::{CancelToken, StopReason};
+use crate::checkpoint::{RenderSettings, RenderState};
 use crate::colour::Colour;
-use crate::common::{self, degrees_to_radians, random_double_range};
+use crate::common::{self, degrees_to_radians};
+use crate::crop::Crop;
+use crate::film::FilmTile;
+use crate::filter::Filter;
+use crate::framebuffer::Framebuffer;
 use crate::hittable::Hittable;
 use crate::hittable::*;
+use crate::material::PathState;
+use crate::medium::{Collision, Medium, MediumStack};
+use crate::progress::{ProgressTracker, RenderObserver, Silent};
 use crate::ray::Ray;
+use crate::sampler::{Sampler, SamplerKind};
+use crate::spectrum::SampledWavelengths;
+use crate::stats::{self, RenderStats};
 use crate::vec3::*;
 use crate::vec3::{Point3, Vec3};
 
 const IMAGE_WIDTH: i32 = 300;
 const ASPECT_RATIO: f64 = 16.0 / 9.0;
-const IMAGE_HEIGHT: i32 = (IMAGE_WIDTH as f64 / ASPECT_RATIO) as i32;
 const SAMPLES_PER_PIXEL: i32 = 100;
 const MAX_DEPTH: i32 = 5;
+// Scattering events inside a subsurface medium, which do not count as bounces
+const MAX_WALK_STEPS: i32 = 256;
 
 pub struct Camera {
+    image_width: i32,
+    image_height: i32,
+    look_from: Point3,
+    look_at: Point3,
     origin: Point3,
     lower_left_corner: Point3,
     horizontal: Vec3,
@@ -21,20 +43,95 @@ pub struct Camera {
     defocus_disk_basis_u: Vec3,
     defocus_disk_basis_v: Vec3,
     defocus_angle: f64,
+    adaptive: Option<AdaptiveSampling>,
+    sampler: SamplerKind,
+    filter: Filter,
+    samples_per_pass: Option<i32>,
+    seed: u64,
+    cancel: CancelToken,
+    time_budget: Option<Duration>,
+    crop: Option<Crop>,
+    spectral: bool,
+    scene_fingerprint: u64,
+    count_hits: bool,
+}
+
+pub struct RenderOutput {
+    pub image: Framebuffer,
+    pub aovs: AovBuffers,
+    pub stats: RenderStats,
+    // Unnormalised full frame for merging with other renders
+    pub accumulation: Accumulation,
+    pub stop_reason: StopReason,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Camera {
     pub fn new() -> Camera {
+        let mut camera = Camera {
+            image_width: 0,
+            image_height: 0,
+            look_from: Point3::default(),
+            look_at: Point3::default(),
+            origin: Point3::default(),
+            lower_left_corner: Point3::default(),
+            horizontal: Vec3::default(),
+            vertical: Vec3::default(),
+            defocus_disk_basis_u: Vec3::default(),
+            defocus_disk_basis_v: Vec3::default(),
+            defocus_angle: 0.6,
+            adaptive: None,
+            sampler: SamplerKind::Independent,
+            filter: Filter::default(),
+            samples_per_pass: None,
+            seed: 0,
+            cancel: CancelToken::new(),
+            time_budget: None,
+            crop: None,
+            spectral: false,
+            scene_fingerprint: 0,
+            count_hits: false,
+        };
+        camera.set_image_width(IMAGE_WIDTH as usize);
+        camera.set_view(Point3::new(13.0, 2.0, 3.0), Point3::new(0.0, 0.0, -1.0));
+        camera
+    }
+
+    // The height follows from the fixed aspect ratio
+    pub fn set_image_width(&mut self, width: usize) {
+        self.image_width = width.max(2) as i32;
+        self.image_height = ((width as f64 / ASPECT_RATIO) as i32).max(2);
+    }
+
+    pub fn image_width(&self) -> usize {
+        self.image_width as usize
+    }
+
+    pub fn image_height(&self) -> usize {
+        self.image_height as usize
+    }
+
+    pub fn look_from(&self) -> Point3 {
+        self.look_from
+    }
+
+    pub fn look_at(&self) -> Point3 {
+        self.look_at
+    }
+
+    pub fn set_view(&mut self, look_from: Point3, look_at: Point3) {
         let v_fov = 20.0;
         let v_fov_radians = degrees_to_radians(v_fov);
         let h = f64::tan(v_fov_radians / 2.0);
 
-        let look_from = Point3::new(13.0, 2.0, 3.0);
-        let look_at = Point3::new(0.0, 0.0, -1.0);
         let v_up = Vec3::new(0.0, 1.0, 0.0);
 
         // Defocus disk
-        let defocus_angle = 0.6;
         let focus_distance = 10.0;
 
         // Basis vectors for camera coordinate frame
@@ -52,29 +149,104 @@ impl Camera {
         let lower_left_corner = origin - viewport_u / 2.0 - viewport_v / 2.0 - w * focus_distance;
 
         // Calculate the camera defocus disk basis vectors.
-        let defocus_radius = focus_distance * f64::tan(degrees_to_radians(defocus_angle / 2.0));
-        let defocus_disk_u = u * defocus_radius;
-        let defocus_disk_v = v * defocus_radius;
+        let defocus_radius =
+            focus_distance * f64::tan(degrees_to_radians(self.defocus_angle / 2.0));
+
+        self.look_from = look_from;
+        self.look_at = look_at;
+        self.origin = origin;
+        self.lower_left_corner = lower_left_corner;
+        self.horizontal = viewport_u;
+        self.vertical = viewport_v;
+        self.defocus_disk_basis_u = u * defocus_radius;
+        self.defocus_disk_basis_v = v * defocus_radius;
+    }
+
+    pub fn set_adaptive_sampling(&mut self, adaptive: Option<AdaptiveSampling>) {
+        self.adaptive = adaptive;
+    }
+
+    pub fn set_sampler(&mut self, sampler: SamplerKind) {
+        self.sampler = sampler;
+    }
+
+    pub fn set_filter(&mut self, filter: Filter) {
+        self.filter = filter;
+    }
+
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+
+    // Progressive mode renders the whole image in passes of this many samples
+    pub fn set_progressive(&mut self, samples_per_pass: Option<i32>) {
+        self.samples_per_pass = samples_per_pass;
+    }
+
+    // Cancelling the token stops the render after the current sample
+    pub fn set_cancel_token(&mut self, cancel: CancelToken) {
+        self.cancel = cancel;
+    }
+
+    // Stops the render once this much time has passed since it started or resumed
+    pub fn set_time_budget(&mut self, time_budget: Option<Duration>) {
+        self.time_budget = time_budget;
+    }
 
-        Camera {
-            origin,
-            lower_left_corner,
-            horizontal: viewport_u,
-            vertical: viewport_v,
-            defocus_disk_basis_u: defocus_disk_u,
-            defocus_disk_basis_v: defocus_disk_v,
-            defocus_angle,
+    // Renders only this window of the frame, set it after the image width
+    pub fn set_crop(&mut self, crop: Option<Crop>) {
+        if let Some(crop) = crop {
+            assert!(
+                crop.width > 0
+                    && crop.height > 0
+                    && crop.x + crop.width <= self.image_width()
+                    && crop.y + crop.height <= self.image_height(),
+                "Crop window must lie inside the image"
+            );
         }
+        self.crop = crop;
     }
 
-    pub fn get_ray(&self, u: f64, v: f64) -> Ray {
+    // Traces hero wavelengths instead of RGB so dispersive glass splits light
+    pub fn set_spectral(&mut self, spectral: bool) {
+        self.spectral = spectral;
+    }
+
+    // Counts `hit` calls per primitive for the render statistics, which
+    // slows rendering down noticeably
+    pub fn set_count_hits(&mut self, count_hits: bool) {
+        self.count_hits = count_hits;
+    }
+
+    // Identifies the world being rendered in saved renders, see Scene::fingerprint
+    pub fn set_scene_fingerprint(&mut self, fingerprint: u64) {
+        self.scene_fingerprint = fingerprint;
+    }
+
+    // Covers the scene and every setting that changes what a sample adds to
+    // the film, renders with the same fingerprint can be merged
+    pub fn fingerprint(&self) -> u64 {
+        let settings = format!(
+            "{} {} {} {}",
+            self.sampler.name(),
+            self.filter.kind().name(),
+            self.filter.radius(),
+            self.spectral
+        );
+        common::mix_seed(
+            self.scene_fingerprint,
+            common::hash_bytes(settings.as_bytes()),
+        )
+    }
+
+    pub fn get_ray(&self, u: f64, v: f64, sampler: &mut dyn Sampler) -> Ray {
         let ray_origin = if self.defocus_angle <= 0.0 {
             self.origin
         } else {
-            self.defocus_disk_sample()
+            self.defocus_disk_sample(sampler.get_2d())
         };
 
-        let ray_time = random_double_range(0.0, 1.0);
+        let ray_time = sampler.get_1d();
 
         Ray::new_tm(
             ray_origin,
@@ -83,56 +255,627 @@ impl Camera {
         )
     }
 
-    fn defocus_disk_sample(&self) -> Vec3 {
-        let p = random_in_unit_disk();
+    fn defocus_disk_sample(&self, u: (f64, f64)) -> Vec3 {
+        let p = sample_unit_disk(u);
         self.origin + p.x() * self.defocus_disk_basis_u + p.y() * self.defocus_disk_basis_v
     }
 
-    fn ray_colour(r: &Ray, world: &dyn Hittable, depth: i32) -> Colour {
+    #[allow(clippy::too_many_arguments)]
+    fn ray_colour(
+        r: &Ray,
+        world: &dyn Hittable,
+        depth: i32,
+        sampler: &mut dyn Sampler,
+        stats: &mut RenderStats,
+        media: &mut MediumStack,
+        mut wavelengths: Option<&mut SampledWavelengths>,
+        aov: Option<&mut AovPixel>,
+    ) -> Colour {
         let mut rec = HitRecord::new();
 
         if depth <= 0 {
+            stats.max_depth_terminations += 1;
             return Colour::new(0.0, 0.0, 0.0);
         }
-        if world.hit(r, 0.001, common::INFINITY, &mut rec) {
+        if depth == MAX_DEPTH {
+            stats.primary_rays += 1;
+        } else {
+            stats.secondary_rays += 1;
+        }
+        stats.path_segments += 1;
+        let mut r = Ray::new_tm(r.origin, r.direction, r.tm);
+        let mut hit = world.hit(&r, 0.001, common::INFINITY, &mut rec);
+        // Only passed for the camera ray, whose hit the AOVs record
+        if let Some(aov) = aov {
+            aov.add(&r, hit.then_some(&rec), Self::background(&r));
+        }
+        let mut walk_weight = Colour::new(1.0, 1.0, 1.0);
+        if let Some(medium) = media.current().filter(Medium::is_scattering) {
+            match Self::random_walk(&medium, &mut r, &mut rec, world, sampler, stats) {
+                Some(weight) => (walk_weight, hit) = (weight, true),
+                None => return Colour::new(0.0, 0.0, 0.0),
+            }
+        }
+        if hit {
+            // Absorption along the segment that led to this hit
+            let mut transmittance = match media.current() {
+                Some(medium) if !medium.is_scattering() => {
+                    medium.transmittance(rec.t * r.direction.length())
+                }
+                _ => walk_weight,
+            };
+            let mat = rec.mat.clone().unwrap();
+            let medium = mat.medium();
+            let path = PathState {
+                exterior_ior: media.exterior_ior(rec.object_id, rec.front_face),
+                wavelength: wavelengths.as_ref().map(|w| w.hero()),
+            };
+
             let mut attenuation = Colour::default();
             let mut scattered = Ray::default();
-            if rec
-                .mat
-                .as_ref()
-                .unwrap()
-                .scatter(r, &rec, &mut attenuation, &mut scattered)
-            {
-                return Self::ray_colour(&scattered, world, depth - 1) * attenuation;
+            if mat.scatter(&r, &rec, &path, &mut attenuation, &mut scattered, sampler) {
+                // Refracting through the surface moves the path into or out of the medium
+                if let Some(medium) = medium {
+                    if scattered.direction.dot(&rec.normal) < 0.0 {
+                        if rec.front_face {
+                            media.enter(rec.object_id, medium);
+                        } else {
+                            media.exit(rec.object_id);
+                        }
+                    }
+                }
+                if let Some(wavelengths) = wavelengths.as_deref_mut() {
+                    if mat.is_dispersive() {
+                        wavelengths.terminate_secondary();
+                    }
+                    attenuation = wavelengths.upsample(attenuation);
+                    transmittance = wavelengths.upsample(transmittance);
+                }
+                let incoming = Self::ray_colour(
+                    &scattered,
+                    world,
+                    depth - 1,
+                    sampler,
+                    stats,
+                    media,
+                    wavelengths,
+                    None,
+                );
+                return incoming * attenuation * transmittance;
             }
+            stats.absorbed += 1;
             return Colour::new(0.0, 0.0, 0.0);
         }
 
+        stats.escaped += 1;
+        let background = Self::background(&r);
+        wavelengths.map_or(background, |w| w.upsample(background))
+    }
+
+    // Follows a path scattering around inside a medium until it reaches the
+    // boundary, leaving `r` and `rec` at that last segment and hit. None if
+    // it scattered too often to be worth following.
+    fn random_walk(
+        medium: &Medium,
+        r: &mut Ray,
+        rec: &mut HitRecord,
+        world: &dyn Hittable,
+        sampler: &mut dyn Sampler,
+        stats: &mut RenderStats,
+    ) -> Option<Colour> {
+        let mut weight = Colour::new(1.0, 1.0, 1.0);
+        for _ in 0..MAX_WALK_STEPS {
+            let length = r.direction.length();
+            let max_distance = if rec.mat.is_some() {
+                rec.t * length
+            } else {
+                common::INFINITY
+            };
+            match medium.sample_collision(max_distance, weight, sampler.get_2d()) {
+                Collision::Surface { weight: surface } => return Some(weight * surface),
+                Collision::Scatter {
+                    distance,
+                    weight: scatter,
+                } => {
+                    weight = weight * scatter;
+                    let origin = r.at(distance / length);
+                    *r = Ray::new_tm(
+                        origin,
+                        medium.sample_phase(r.direction, sampler.get_2d()),
+                        r.tm,
+                    );
+                    *rec = HitRecord::new();
+                    world.hit(r, 0.001, common::INFINITY, rec);
+                    stats.secondary_rays += 1;
+                    stats.path_segments += 1;
+                }
+            }
+        }
+        stats.walk_terminations += 1;
+        None
+    }
+
+    fn background(r: &Ray) -> Colour {
         let unit_direction = unit_vector(r.direction);
         let t = (1.0 - 0.5) * (unit_direction.y() + 1.0);
 
         (1.0 - t) * Colour::new(1.0, 1.0, 1.0) + t * Colour::new(0.5, 0.7, 1.0)
     }
 
-    pub fn render(&self, world: &dyn Hittable) {
-        println!("P3\n{} {}\n255", IMAGE_WIDTH, IMAGE_HEIGHT);
+    pub fn render(&self, world: &dyn Hittable, aovs: &[Aov]) -> RenderOutput {
+        self.render_progressive(world, aovs, None, &mut Silent)
+    }
+
+    // Renders whole-image passes and reports progress and the accumulated
+    // state after each one to the observer. Without progressive mode there is
+    // a single pass. Passing a saved state continues that render instead of
+    // starting over. A cancelled render returns what has been accumulated so
+    // far, the observer still sees the partial state as a final pass.
+    pub fn render_progressive(
+        &self,
+        world: &dyn Hittable,
+        aovs: &[Aov],
+        resume: Option<RenderState>,
+        observer: &mut dyn RenderObserver,
+    ) -> RenderOutput {
+        let mut state = resume.unwrap_or_else(|| self.new_render_state());
+        assert!(
+            state.film.width() == self.image_width() && state.film.height() == self.image_height(),
+            "Checkpoint resolution does not match the camera"
+        );
+        if let Some(setting) = state.settings.mismatch(&self.settings()) {
+            panic!("Checkpoint was rendered with a different {}", setting);
+        }
 
-        for j in (0..IMAGE_HEIGHT).rev() {
-            eprint!("\rScan lines remaining: {}", j);
-            for i in 0..IMAGE_WIDTH {
-                let mut pixel_colour = Colour::new(0.0, 0.0, 0.0);
+        while !self.is_finished(&state) {
+            let completed = self.render_pass(world, aovs, &mut state, observer);
+            observer.on_pass(&state);
+            if !completed {
+                break;
+            }
+        }
+        let (columns, rows) = self.render_region();
+        let pixels = columns.len() * rows.len();
+        let fraction = if self.is_finished(&state) {
+            1.0
+        } else {
+            self.fraction_done(&state)
+        };
+        observer.on_finish(&state.tracker.progress(pixels, pixels, fraction));
 
-                for _ in 0..SAMPLES_PER_PIXEL {
-                    let u = (i as f64 + common::random_double()) / (IMAGE_WIDTH - 1) as f64;
-                    let v = (j as f64 + common::random_double()) / (IMAGE_HEIGHT - 1) as f64;
-                    let r = self.get_ray(u, v);
+        self.output(&state, aovs)
+    }
 
-                    pixel_colour += Self::ray_colour(&r, world, MAX_DEPTH);
+    pub fn new_render_state(&self) -> RenderState {
+        RenderState::new(
+            self.seed,
+            self.settings(),
+            self.image_width(),
+            self.image_height(),
+        )
+    }
+
+    pub fn settings(&self) -> RenderSettings {
+        RenderSettings {
+            sampler: self.sampler,
+            filter: self.filter,
+            crop: self.crop,
+            adaptive: self.adaptive,
+            samples_per_pixel: self.max_samples(),
+            samples_per_pass: self.samples_per_pass(),
+            spectral: self.spectral,
+            scene_fingerprint: self.scene_fingerprint,
+        }
+    }
+
+    pub fn is_finished(&self, state: &RenderState) -> bool {
+        state.samples_done >= self.max_samples()
+    }
+
+    pub fn max_samples(&self) -> i32 {
+        self.adaptive.map_or(SAMPLES_PER_PIXEL, |a| a.max_samples)
+    }
+
+    fn samples_per_pass(&self) -> i32 {
+        self.samples_per_pass.unwrap_or(self.max_samples())
+    }
+
+    fn render_region(&self) -> (Range<usize>, Range<usize>) {
+        match self.crop {
+            Some(crop) => crop.render_region(self.image_width(), self.image_height()),
+            None => (0..self.image_width(), 0..self.image_height()),
+        }
+    }
+
+    fn fraction_done(&self, state: &RenderState) -> f64 {
+        let (columns, rows) = self.render_region();
+        let width = self.image_width();
+        let samples: i64 = rows
+            .clone()
+            .flat_map(|y| columns.clone().map(move |x| y * width + x))
+            .map(|i| state.pixels[i].samples as i64)
+            .sum();
+        samples as f64 / ((columns.len() * rows.len()) as i64 * self.max_samples() as i64) as f64
+    }
+
+    fn stop_reason(&self, state: &RenderState) -> StopReason {
+        if self.is_finished(state) {
+            StopReason::Complete
+        } else if self.cancel.is_cancelled() {
+            StopReason::Cancelled
+        } else {
+            StopReason::TimeBudget
+        }
+    }
+
+    fn should_stop(&self, tracker: &ProgressTracker) -> bool {
+        self.cancel.is_cancelled()
+            || self
+                .time_budget
+                .is_some_and(|budget| tracker.elapsed() >= budget)
+    }
+
+    // Adds one pass of samples to every unconverged pixel of the render
+    // region. Returns false if
+    // the pass was stopped early, the pixels keep their own sample counts so
+    // the next pass picks up where this one stopped.
+    pub fn render_pass(
+        &self,
+        world: &dyn Hittable,
+        aovs: &[Aov],
+        state: &mut RenderState,
+        observer: &mut dyn RenderObserver,
+    ) -> bool {
+        let max_samples = self.max_samples();
+        let samples_per_pass = self.samples_per_pass();
+        let pass_end = (state.samples_done + samples_per_pass).min(max_samples);
+        state
+            .tracker
+            .start(state.samples_done as f64 / max_samples as f64);
+
+        let completed =
+            self.sample_pixels(world, aovs, state, observer, self.render_region(), pass_end);
+        if completed {
+            state.samples_done = pass_end;
+        }
+        completed
+    }
+
+    // The pixels the samples of a tile splat into, which reach past the tile
+    // by the filter radius
+    pub fn tile_bounds(&self, tile: Crop) -> Crop {
+        let reach = self.filter.radius().ceil() as usize;
+        let (x, y) = (tile.x.saturating_sub(reach), tile.y.saturating_sub(reach));
+        Crop::new(
+            x,
+            y,
+            (tile.x + tile.width + reach).min(self.image_width()) - x,
+            (tile.y + tile.height + reach).min(self.image_height()) - y,
+        )
+    }
+
+    // Renders samples `samples` of every pixel in the tile and returns the
+    // accumulation of the tile's bounds
+    pub fn render_tile(&self, world: &dyn Hittable, tile: Crop, samples: Range<i32>) -> FilmTile {
+        let bounds = self.tile_bounds(tile);
+        let mut state = RenderState::window(self.seed, self.settings(), bounds);
+        state.samples_done = samples.start;
+        for pixel in &mut state.pixels {
+            pixel.samples = samples.start;
+        }
+        let region = (tile.x..tile.x + tile.width, tile.y..tile.y + tile.height);
+        self.sample_pixels(world, &[], &mut state, &mut Silent, region, samples.end);
+        state
+            .film
+            .tile(bounds.x, bounds.y, bounds.width, bounds.height)
+    }
+
+    // Takes every pixel of the region up to `pass_end` samples, stopping
+    // early if cancelled
+    fn sample_pixels(
+        &self,
+        world: &dyn Hittable,
+        aovs: &[Aov],
+        state: &mut RenderState,
+        observer: &mut dyn RenderObserver,
+        (columns, rows): (Range<usize>, Range<usize>),
+        pass_end: i32,
+    ) -> bool {
+        let width = self.image_width();
+        let pixels = columns.len() * rows.len();
+        let max_samples = self.max_samples();
+        let mut sampler = self.sampler.create(max_samples as u32);
+        stats::set_counting(self.count_hits);
+        let hit_calls_before = stats::hit_calls();
+        let mut completed = true;
+
+        'rows: for (row, y) in rows.clone().enumerate() {
+            for x in columns.clone() {
+                let (i, j) = (x as i32, self.image_height - 1 - y as i32);
+                let pixel_seed = common::mix_seed(state.seed, (y * width + x) as u64);
+                let pixel = &mut state.pixels[state.film.index(x, y)];
+
+                while pixel.samples < pass_end
+                    && !self.adaptive.is_some_and(|a| a.converged(&pixel.variance))
+                {
+                    if self.should_stop(&state.tracker) {
+                        completed = false;
+                        break 'rows;
+                    }
+                    // Every sample gets its own random sequence so a resumed
+                    // render draws exactly the numbers it would have drawn
+                    common::seed_random(common::mix_seed(pixel_seed, pixel.samples as u64));
+                    sampler.start_sample(x, y, pixel.samples as u32);
+                    let (jitter_u, jitter_v) = sampler.get_2d();
+                    let u = (i as f64 + jitter_u) / (self.image_width - 1) as f64;
+                    let v = (j as f64 + jitter_v) / (self.image_height - 1) as f64;
+                    let r = self.get_ray(u, v, sampler.as_mut());
+
+                    let mut wavelengths = self
+                        .spectral
+                        .then(|| SampledWavelengths::sample(sampler.get_1d()));
+                    let radiance = Self::ray_colour(
+                        &r,
+                        world,
+                        MAX_DEPTH,
+                        sampler.as_mut(),
+                        &mut state.tracker.stats,
+                        &mut MediumStack::new(),
+                        wavelengths.as_mut(),
+                        (!aovs.is_empty()).then_some(&mut pixel.aov),
+                    );
+                    let sample = wavelengths.map_or(radiance, |w| w.to_rgb(radiance));
+                    state
+                        .film
+                        .add_sample(x as f64 + jitter_u, y as f64 + 1.0 - jitter_v, sample);
+                    pixel.variance.add(sample);
+                    pixel.samples += 1;
+                    state.tracker.samples += 1;
+                    state.tracker.stats.paths += 1;
+                }
+            }
+
+            let pixels_done = (row + 1) * columns.len();
+            let pass_fraction = pixels_done as f64 / pixels as f64;
+            let fraction = (state.samples_done as f64
+                + (pass_end - state.samples_done) as f64 * pass_fraction)
+                / max_samples as f64;
+            observer.on_progress(&state.tracker.progress(pixels_done, pixels, fraction));
+            observer.on_row(&state.film);
+        }
+
+        for (total, (after, before)) in state
+            .tracker
+            .stats
+            .hit_calls
+            .iter_mut()
+            .zip(stats::hit_calls().iter().zip(hit_calls_before))
+        {
+            *total += after - before;
+        }
+        completed
+    }
+
+    // With a crop window the output is either just the window or the full
+    // frame with black outside it, the border is never part of the output
+    pub fn output(&self, state: &RenderState, aovs: &[Aov]) -> RenderOutput {
+        let width = state.film.width();
+        let full = Crop::new(0, 0, width, state.film.height());
+        let crop = self.crop.unwrap_or(full);
+        let frame = if crop.full_frame { full } else { crop };
+
+        let mut image = Framebuffer::new(frame.width, frame.height);
+        let mut aov_buffers = AovBuffers::new(aovs, frame.width, frame.height);
+        for y in crop.y..crop.y + crop.height {
+            for x in crop.x..crop.x + crop.width {
+                let (out_x, out_y) = (x - frame.x, y - frame.y);
+                image.set(out_x, out_y, state.film.pixel(x, y));
+                aov_buffers.store(out_x, out_y, &state.pixels[y * width + x].aov);
+            }
+        }
+
+        RenderOutput {
+            image,
+            aovs: aov_buffers,
+            stats: state.tracker.stats(),
+            accumulation: Accumulation::new(
+                state.film.clone(),
+                state.pixels.iter().map(|p| p.samples as u32).collect(),
+                self.fingerprint(),
+            ),
+            stop_reason: self.stop_reason(state),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::filter::FilterKind;
+    use crate::material::{Lambertian, Material, Subsurface};
+    use crate::sampler::IndependentSampler;
+    use crate::sphere::Sphere;
+
+    // Mean weight of random walks entering a subsurface sphere a hundred
+    // mean free paths across head on, without a refractive boundary, that
+    // make it back out
+    fn walk_albedo(albedo: f64, walks: usize) -> f64 {
+        let material = Subsurface::new(
+            Colour::new(albedo, albedo, albedo),
+            Colour::new(0.02, 0.02, 0.02),
+            0.0,
+            1.0,
+        );
+        let medium = material.medium().unwrap();
+        let mut world = HittableList::new();
+        world.add(Box::new(Sphere::new(
+            Point3::new(0.0, 0.0, 0.0),
+            1.0,
+            Rc::new(material),
+        )));
+
+        common::seed_random(1);
+        let mut stats = RenderStats::default();
+        let mut total = 0.0;
+        for _ in 0..walks {
+            let mut r = Ray::new_tm(Point3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+            let mut rec = HitRecord::new();
+            assert!(world.hit(&r, 0.001, common::INFINITY, &mut rec));
+            let walk = Camera::random_walk(
+                &medium,
+                &mut r,
+                &mut rec,
+                &world,
+                &mut IndependentSampler,
+                &mut stats,
+            );
+            if let Some(weight) = walk {
+                // Only walks that end on the entry side got back out
+                if r.at(rec.t).z() > 0.5 {
+                    total += weight.x();
                 }
+            }
+        }
+        total / walks as f64
+    }
+
+    // The albedo fit is approximate and walks cut off at the step limit count
+    // as absorbed, so the exiting energy only lands near the requested albedo
+    #[test]
+    fn random_walk_exits_with_albedo() {
+        for albedo in [0.2, 0.5, 0.8] {
+            let exiting = walk_albedo(albedo, 4000);
+            assert!(
+                (exiting - albedo).abs() < 0.07,
+                "albedo {} came out as {}",
+                albedo,
+                exiting
+            );
+        }
+    }
+
+    fn crop_world() -> HittableList {
+        let mut world = HittableList::new();
+        world.add(Box::new(Sphere::new(
+            Point3::new(0.0, 1.0, 0.0),
+            1.5,
+            Rc::new(Lambertian::new(Colour::new(0.8, 0.3, 0.2))),
+        )));
+        world
+    }
+
+    fn crop_camera(crop: Option<Crop>) -> Camera {
+        let mut cam = Camera::new();
+        cam.set_image_width(32);
+        cam.set_filter(Filter::new(FilterKind::Gaussian));
+        cam.set_seed(3);
+        cam.set_crop(crop);
+        cam
+    }
+
+    // The window of the frame starting at (x, y) is identical to the same
+    // pixels of the full render
+    fn assert_window_matches(image: &Framebuffer, full: &Framebuffer, x: usize, y: usize) {
+        for row in 0..image.height() {
+            for column in 0..image.width() {
+                assert_eq!(image.get(column, row), full.get(x + column, y + row));
+            }
+        }
+    }
+
+    #[test]
+    fn crop_with_border_matches_full_render() {
+        let world = crop_world();
+        let full = crop_camera(None).render(&world, &[]).image;
 
-                colour::write_colour(&mut std::io::stdout(), pixel_colour, SAMPLES_PER_PIXEL);
+        // The Gaussian reaches two pixels, a border that wide takes every
+        // sample splatting into the window
+        let mut crop = Crop::new(9, 4, 10, 6);
+        crop.border = 2;
+        let image = crop_camera(Some(crop)).render(&world, &[]).image;
+        assert_eq!((image.width(), image.height()), (10, 6));
+        assert_window_matches(&image, &full, 9, 4);
+
+        // Without the border the window's edges miss samples from outside it
+        let image = crop_camera(Some(Crop::new(9, 4, 10, 6)))
+            .render(&world, &[])
+            .image;
+        assert_ne!(image.get(0, 0), full.get(9, 4));
+    }
+
+    #[test]
+    fn full_frame_crop_is_black_outside_window() {
+        let world = crop_world();
+        let full = crop_camera(None).render(&world, &[]).image;
+
+        let mut crop = Crop::new(9, 4, 10, 6);
+        crop.border = 2;
+        crop.full_frame = true;
+        let image = crop_camera(Some(crop)).render(&world, &[]).image;
+        assert_eq!(
+            (image.width(), image.height()),
+            (full.width(), full.height())
+        );
+        for y in 0..image.height() {
+            for x in 0..image.width() {
+                if (9..19).contains(&x) && (4..10).contains(&y) {
+                    assert_eq!(image.get(x, y), full.get(x, y));
+                } else {
+                    assert_eq!(image.get(x, y), Colour::default());
+                }
+            }
+        }
+    }
+
+    // Keeps the image after every pass and counts rows
+    #[derive(Default)]
+    struct Recording {
+        passes: Vec<(i32, Framebuffer)>,
+        rows: usize,
+    }
+
+    impl RenderObserver for Recording {
+        fn on_row(&mut self, _film: &crate::film::Film) {
+            self.rows += 1;
+        }
+
+        fn on_pass(&mut self, state: &RenderState) {
+            self.passes
+                .push((state.samples_done(), state.film().to_framebuffer()));
+        }
+    }
+
+    #[test]
+    fn pass_snapshots_average_samples_so_far() {
+        let world = crop_world();
+        let mut cam = crop_camera(None);
+        cam.set_progressive(Some(25));
+        let mut recording = Recording::default();
+        let output = cam.render_progressive(&world, &[], None, &mut recording);
+
+        let samples: Vec<i32> = recording.passes.iter().map(|(s, _)| *s).collect();
+        assert_eq!(samples, [25, 50, 75, 100]);
+        assert_eq!(recording.rows, 4 * cam.image_height());
+        let (_, last) = recording.passes.last().unwrap();
+        assert_window_matches(last, &output.image, 0, 0);
+
+        // A pass snapshot is what a render stopping at that many samples
+        // gives, up to the order the sums were added in
+        for (samples, snapshot) in &recording.passes {
+            let mut shorter = crop_camera(None);
+            shorter.set_adaptive_sampling(Some(AdaptiveSampling {
+                min_samples: *samples,
+                max_samples: *samples,
+                threshold: 0.0,
+            }));
+            let image = shorter.render(&world, &[]).image;
+            for y in 0..image.height() {
+                for x in 0..image.width() {
+                    assert!((image.get(x, y) - snapshot.get(x, y)).length() < 1e-12);
+                }
             }
         }
-        eprintln!("\nDone!");
     }
 }
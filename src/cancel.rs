@@ -0,0 +1,65 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+// Shared flag for stopping a render from another thread. Clones refer to the
+// same flag, the renderer checks it between samples.
+#[derive(Clone, Default, Debug)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    pub fn new() -> CancelToken {
+        Default::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+// Why a render returned, anything but Complete leaves a partial image
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StopReason {
+    Complete,
+    Cancelled,
+    TimeBudget,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::camera::Camera;
+    use crate::hittable::HittableList;
+
+    fn camera() -> Camera {
+        let mut cam = Camera::new();
+        cam.set_image_width(8);
+        cam
+    }
+
+    #[test]
+    fn reports_why_the_render_stopped() {
+        let world = HittableList::new();
+        assert_eq!(
+            camera().render(&world, &[]).stop_reason,
+            StopReason::Complete
+        );
+
+        let mut cam = camera();
+        let cancel = CancelToken::new();
+        cam.set_cancel_token(cancel.clone());
+        cancel.cancel();
+        assert_eq!(cam.render(&world, &[]).stop_reason, StopReason::Cancelled);
+
+        let mut cam = camera();
+        cam.set_time_budget(Some(Duration::ZERO));
+        assert_eq!(cam.render(&world, &[]).stop_reason, StopReason::TimeBudget);
+    }
+}
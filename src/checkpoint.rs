@@ -0,0 +1,438 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+
+use crate::adaptive::{AdaptiveSampling, Welford};
+use crate::aov::AovPixel;
+use crate::crop::Crop;
+use crate::film::Film;
+use crate::filter::{Filter, FilterKind};
+use crate::progress::ProgressTracker;
+use crate::sampler::SamplerKind;
+use crate::vec3::Vec3;
+
+const MAGIC: &[u8; 4] = b"RTCK";
+const VERSION: u32 = 3;
+
+// Camera settings that decide which samples a render takes and what they
+// add to the film. A render only resumes from a checkpoint taken with the
+// same settings, anything else would mix incompatible samples.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct RenderSettings {
+    pub sampler: SamplerKind,
+    pub filter: Filter,
+    pub crop: Option<Crop>,
+    pub adaptive: Option<AdaptiveSampling>,
+    pub samples_per_pixel: i32,
+    // Passes splat their samples in turn, so the pass length decides the
+    // order the film's sums are added up in
+    pub samples_per_pass: i32,
+    pub spectral: bool,
+    // See Camera::set_scene_fingerprint
+    pub scene_fingerprint: u64,
+}
+
+impl RenderSettings {
+    // Name of the first setting that differs, for telling the user what to change
+    pub fn mismatch(&self, other: &RenderSettings) -> Option<&'static str> {
+        [
+            (self.sampler != other.sampler, "sampler"),
+            (self.filter != other.filter, "filter"),
+            (self.crop != other.crop, "crop window"),
+            (self.adaptive != other.adaptive, "adaptive sampling"),
+            (
+                self.samples_per_pixel != other.samples_per_pixel,
+                "sample count",
+            ),
+            (
+                self.samples_per_pass != other.samples_per_pass,
+                "samples per pass",
+            ),
+            (self.spectral != other.spectral, "spectral setting"),
+            (self.scene_fingerprint != other.scene_fingerprint, "scene"),
+        ]
+        .into_iter()
+        .find(|(differs, _)| *differs)
+        .map(|(_, name)| name)
+    }
+
+    fn write(&self, out: &mut impl Write) -> io::Result<()> {
+        write_string(out, self.sampler.name())?;
+        write_string(out, self.filter.kind().name())?;
+        write_f64(out, self.filter.radius())?;
+        write_u32(out, self.crop.is_some() as u32)?;
+        if let Some(crop) = self.crop {
+            for v in [crop.x, crop.y, crop.width, crop.height, crop.border] {
+                write_u32(out, v as u32)?;
+            }
+            write_u32(out, crop.full_frame as u32)?;
+        }
+        write_u32(out, self.adaptive.is_some() as u32)?;
+        if let Some(adaptive) = self.adaptive {
+            write_u32(out, adaptive.min_samples as u32)?;
+            write_u32(out, adaptive.max_samples as u32)?;
+            write_f64(out, adaptive.threshold)?;
+        }
+        write_u32(out, self.samples_per_pixel as u32)?;
+        write_u32(out, self.samples_per_pass as u32)?;
+        write_u32(out, self.spectral as u32)?;
+        write_u64(out, self.scene_fingerprint)
+    }
+
+    fn read(input: &mut impl Read) -> io::Result<RenderSettings> {
+        let sampler = SamplerKind::from_name(&read_string(input)?)
+            .ok_or_else(|| invalid_data("unknown sampler"))?;
+        let filter_kind = FilterKind::from_name(&read_string(input)?)
+            .ok_or_else(|| invalid_data("unknown filter"))?;
+        let radius = read_f64(input)?;
+        if !radius.is_finite() || radius <= 0.0 {
+            return Err(invalid_data("invalid filter radius"));
+        }
+        let filter = Filter::with_radius(filter_kind, radius);
+        let crop = match read_u32(input)? {
+            0 => None,
+            _ => {
+                let mut crop = Crop::new(
+                    read_u32(input)? as usize,
+                    read_u32(input)? as usize,
+                    read_u32(input)? as usize,
+                    read_u32(input)? as usize,
+                );
+                crop.border = read_u32(input)? as usize;
+                crop.full_frame = read_u32(input)? != 0;
+                Some(crop)
+            }
+        };
+        let adaptive = match read_u32(input)? {
+            0 => None,
+            _ => Some(AdaptiveSampling {
+                min_samples: read_u32(input)? as i32,
+                max_samples: read_u32(input)? as i32,
+                threshold: read_f64(input)?,
+            }),
+        };
+        Ok(RenderSettings {
+            sampler,
+            filter,
+            crop,
+            adaptive,
+            samples_per_pixel: read_u32(input)? as i32,
+            samples_per_pass: read_u32(input)? as i32,
+            spectral: read_u32(input)? != 0,
+            scene_fingerprint: read_u64(input)?,
+        })
+    }
+}
+
+// Everything needed to continue a render exactly where it stopped. Together
+// with the seed the per-sample RNG reseeding makes a resumed render match an
+// uninterrupted one bit for bit.
+pub struct RenderState {
+    pub(crate) seed: u64,
+    pub(crate) settings: RenderSettings,
+    pub(crate) samples_done: i32,
+    pub(crate) film: Film,
+    pub(crate) pixels: Vec<PixelState>,
+    pub(crate) tracker: ProgressTracker,
+}
+
+impl RenderState {
+    pub(crate) fn new(
+        seed: u64,
+        settings: RenderSettings,
+        width: usize,
+        height: usize,
+    ) -> RenderState {
+        RenderState::window(seed, settings, Crop::new(0, 0, width, height))
+    }
+
+    // State of just a window of the frame, see Film::window
+    pub(crate) fn window(seed: u64, settings: RenderSettings, window: Crop) -> RenderState {
+        let (width, height) = (window.width, window.height);
+        RenderState {
+            seed,
+            settings,
+            samples_done: 0,
+            film: Film::window(window.x, window.y, width, height, settings.filter),
+            pixels: (0..width * height).map(|_| PixelState::new()).collect(),
+            tracker: ProgressTracker::new(),
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn settings(&self) -> &RenderSettings {
+        &self.settings
+    }
+
+    // Samples per pixel completed by every finished pass
+    pub fn samples_done(&self) -> i32 {
+        self.samples_done
+    }
+
+    pub fn film(&self) -> &Film {
+        &self.film
+    }
+
+    // Written to a temporary file first so a crash mid-write keeps the old checkpoint
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        let temp_path = format!("{}.tmp", path);
+        let mut out = BufWriter::new(File::create(&temp_path)?);
+        out.write_all(MAGIC)?;
+        write_u32(&mut out, VERSION)?;
+        write_u64(&mut out, self.seed)?;
+        self.settings.write(&mut out)?;
+        write_u32(&mut out, self.samples_done as u32)?;
+        self.film.write(&mut out)?;
+        for pixel in &self.pixels {
+            pixel.write(&mut out)?;
+        }
+        out.flush()?;
+        drop(out);
+        fs::rename(&temp_path, path)
+    }
+
+    pub fn load(path: &str) -> io::Result<RenderState> {
+        let mut input = BufReader::new(File::open(path)?);
+        let mut magic = [0; 4];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC || read_u32(&mut input)? != VERSION {
+            return Err(invalid_data("not a render checkpoint"));
+        }
+        let seed = read_u64(&mut input)?;
+        let settings = RenderSettings::read(&mut input)?;
+        let samples_done = read_u32(&mut input)? as i32;
+        let film = Film::read(&mut input, settings.filter)?;
+        let pixels = (0..film.width() * film.height())
+            .map(|_| PixelState::read(&mut input))
+            .collect::<io::Result<Vec<PixelState>>>()?;
+
+        Ok(RenderState {
+            seed,
+            settings,
+            samples_done,
+            film,
+            pixels,
+            tracker: ProgressTracker::new(),
+        })
+    }
+}
+
+// Sampling state kept per pixel across progressive passes
+pub(crate) struct PixelState {
+    pub samples: i32,
+    pub variance: Welford,
+    pub aov: AovPixel,
+}
+
+impl PixelState {
+    fn new() -> PixelState {
+        PixelState {
+            samples: 0,
+            variance: Welford::new(),
+            aov: AovPixel::new(),
+        }
+    }
+
+    fn write(&self, out: &mut impl Write) -> io::Result<()> {
+        write_u32(out, self.samples as u32)?;
+        self.variance.write(out)?;
+        self.aov.write(out)
+    }
+
+    fn read(input: &mut impl Read) -> io::Result<PixelState> {
+        Ok(PixelState {
+            samples: read_u32(input)? as i32,
+            variance: Welford::read(input)?,
+            aov: AovPixel::read(input)?,
+        })
+    }
+}
+
+pub(crate) fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+pub(crate) fn write_u32(out: &mut impl Write, v: u32) -> io::Result<()> {
+    out.write_all(&v.to_le_bytes())
+}
+
+pub(crate) fn write_u64(out: &mut impl Write, v: u64) -> io::Result<()> {
+    out.write_all(&v.to_le_bytes())
+}
+
+pub(crate) fn write_f64(out: &mut impl Write, v: f64) -> io::Result<()> {
+    out.write_all(&v.to_le_bytes())
+}
+
+pub(crate) fn write_vec3(out: &mut impl Write, v: Vec3) -> io::Result<()> {
+    write_f64(out, v.x())?;
+    write_f64(out, v.y())?;
+    write_f64(out, v.z())
+}
+
+pub(crate) fn write_string(out: &mut impl Write, s: &str) -> io::Result<()> {
+    write_u32(out, s.len() as u32)?;
+    out.write_all(s.as_bytes())
+}
+
+pub(crate) fn read_u32(input: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+pub(crate) fn read_u64(input: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+pub(crate) fn read_f64(input: &mut impl Read) -> io::Result<f64> {
+    let mut bytes = [0; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(f64::from_le_bytes(bytes))
+}
+
+pub(crate) fn read_vec3(input: &mut impl Read) -> io::Result<Vec3> {
+    Ok(Vec3::new(
+        read_f64(input)?,
+        read_f64(input)?,
+        read_f64(input)?,
+    ))
+}
+
+pub(crate) fn read_string(input: &mut impl Read) -> io::Result<String> {
+    let mut bytes = vec![0; read_u32(input)? as usize];
+    input.read_exact(&mut bytes)?;
+    String::from_utf8(bytes).map_err(|_| invalid_data("invalid string"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aov::Aov;
+    use crate::camera::Camera;
+    use crate::colour::Colour;
+    use crate::framebuffer::Framebuffer;
+    use crate::hittable::HittableList;
+    use crate::progress::Silent;
+    use crate::scene::{MaterialDesc, Scene};
+    use crate::vec3::Point3;
+
+    fn world() -> HittableList {
+        let mut scene = Scene::new();
+        scene.add_sphere(
+            Point3::new(0.0, -1000.0, 0.0),
+            1000.0,
+            MaterialDesc::Lambertian {
+                albedo: Colour::new(0.5, 0.5, 0.5),
+            },
+        );
+        scene.add_sphere(
+            Point3::new(0.0, 1.0, 0.0),
+            1.0,
+            MaterialDesc::Metal {
+                reflectance: Colour::new(0.7, 0.6, 0.5),
+                roughness: 0.3,
+                film: None,
+            },
+        );
+        scene.build()
+    }
+
+    fn camera(samples_per_pass: i32) -> Camera {
+        let mut cam = Camera::new();
+        cam.set_image_width(24);
+        cam.set_filter(Filter::new(FilterKind::Mitchell));
+        cam.set_sampler(SamplerKind::Sobol);
+        cam.set_seed(5);
+        cam.set_progressive(Some(samples_per_pass));
+        cam
+    }
+
+    fn ppm(image: &Framebuffer) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        image.write_ppm(&mut bytes);
+        bytes
+    }
+
+    fn assert_identical(a: &Framebuffer, b: &Framebuffer) {
+        for y in 0..a.height() {
+            for x in 0..a.width() {
+                assert_eq!(a.get(x, y), b.get(x, y));
+            }
+        }
+    }
+
+    // Saves a checkpoint after three passes and resumes from the saved file
+    fn resume(cam: &Camera, world: &HittableList, aovs: &[Aov], name: &str) -> RenderState {
+        let mut state = cam.new_render_state();
+        for _ in 0..3 {
+            assert!(cam.render_pass(world, aovs, &mut state, &mut Silent));
+        }
+        let path = std::env::temp_dir().join(format!("{}-{}.rtck", name, std::process::id()));
+        let path = path.to_str().unwrap();
+        state.save(path).unwrap();
+        let loaded = RenderState::load(path).unwrap();
+        fs::remove_file(path).unwrap();
+        loaded
+    }
+
+    #[test]
+    fn resumed_render_matches_uninterrupted_render() {
+        let world = world();
+        let aovs = [Aov::Albedo, Aov::Normal, Aov::Depth];
+        let cam = camera(10);
+        let straight = cam.render(&world, &aovs);
+
+        let state = resume(&cam, &world, &aovs, "resume");
+        assert_eq!(state.samples_done(), 30);
+        let resumed = cam.render_progressive(&world, &aovs, Some(state), &mut Silent);
+
+        assert_eq!(ppm(&resumed.image), ppm(&straight.image));
+        assert_identical(&resumed.image, &straight.image);
+        for aov in aovs {
+            assert_identical(
+                resumed.aovs.get(aov).unwrap(),
+                straight.aovs.get(aov).unwrap(),
+            );
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "different samples per pass")]
+    fn resuming_with_other_pass_length_fails() {
+        let world = world();
+        let state = resume(&camera(10), &world, &[], "pass-length");
+        camera(20).render_progressive(&world, &[], Some(state), &mut Silent);
+    }
+
+    #[test]
+    fn settings_round_trip() {
+        let mut crop = Crop::new(3, 4, 10, 6);
+        crop.border = 2;
+        let settings = RenderSettings {
+            sampler: SamplerKind::Sobol,
+            filter: Filter::with_radius(FilterKind::Mitchell, 1.5),
+            crop: Some(crop),
+            adaptive: Some(AdaptiveSampling::new(0.02)),
+            samples_per_pixel: 256,
+            samples_per_pass: 16,
+            spectral: true,
+            scene_fingerprint: 0x1234_5678_9abc_def0,
+        };
+        let mut bytes = Vec::new();
+        settings.write(&mut bytes).unwrap();
+        let read = RenderSettings::read(&mut bytes.as_slice()).unwrap();
+        assert_eq!(read, settings);
+        assert_eq!(read.mismatch(&settings), None);
+
+        let other = RenderSettings {
+            crop: None,
+            ..settings
+        };
+        assert_eq!(settings.mismatch(&other), Some("crop window"));
+    }
+}
@@ -7,6 +7,11 @@ use std::io::Write;
 pub type Colour = Vec3;
 
 pub fn write_colour(out: &mut impl Write, pixel_colour: Colour, samples_per_pixel: i32) {
+    let [r, g, b] = to_rgb8(pixel_colour, samples_per_pixel);
+    writeln!(out, "{} {} {}", r, g, b).expect("Writing colour");
+}
+
+pub fn to_rgb8(pixel_colour: Colour, samples_per_pixel: i32) -> [u8; 3] {
     // Divide colour by number of samples
     let scale = 1.0 / samples_per_pixel as f64;
 
@@ -15,11 +20,16 @@ pub fn write_colour(out: &mut impl Write, pixel_colour: Colour, samples_per_pixe
     let g = f64::sqrt(pixel_colour.y() * scale);
     let b = f64::sqrt(pixel_colour.z() * scale);
 
-    let r_clamped = (256.0 * common::clamp(r, 0.0, 0.999)) as i32;
-    let g_clamped = (256.0 * common::clamp(g, 0.0, 0.999)) as i32;
-    let b_clamped = (256.0 * common::clamp(b, 0.0, 0.999)) as i32;
+    let r_clamped = (256.0 * common::clamp(r, 0.0, 0.999)) as u8;
+    let g_clamped = (256.0 * common::clamp(g, 0.0, 0.999)) as u8;
+    let b_clamped = (256.0 * common::clamp(b, 0.0, 0.999)) as u8;
+
+    [r_clamped, g_clamped, b_clamped]
+}
 
-    writeln!(out, "{} {} {}", r_clamped, g_clamped, b_clamped).expect("Writing colour");
+// Rec. 709 relative luminance
+pub fn luminance(c: Colour) -> f64 {
+    0.2126 * c.x() + 0.7152 * c.y() + 0.0722 * c.z()
 }
 
 pub fn random() -> Colour {
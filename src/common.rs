@@ -1,14 +1,43 @@
 pub use std::f64::consts::PI;
-pub use std::f64::INFINITY;
 
-use rand::Rng;
+use std::cell::RefCell;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+pub const INFINITY: f64 = f64::INFINITY;
 
 pub fn degrees_to_radians(degrees: f64) -> f64 {
     (degrees * PI) / 180.0
 }
 
+thread_local! {
+    static RNG: RefCell<StdRng> = RefCell::new(StdRng::from_entropy());
+}
+
 pub fn random_double() -> f64 {
-    rand::thread_rng().gen()
+    RNG.with(|rng| rng.borrow_mut().gen())
+}
+
+// Restarts the random sequence so a render can be reproduced exactly
+pub fn seed_random(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
+}
+
+// SplitMix64 finaliser, used to derive independent seeds from a base seed
+pub fn mix_seed(seed: u64, value: u64) -> u64 {
+    let mut z = seed ^ value.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+// FNV-1a, which unlike the standard library hasher stays the same across
+// builds so the result can be written to files
+pub fn hash_bytes(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
 }
 
 pub fn random_double_range(min: f64, max: f64) -> f64 {
@@ -0,0 +1,44 @@
+use std::ops::Range;
+
+// Rectangle of the full frame to render, in pixels with row 0 at the top. The
+// camera geometry stays that of the full frame. The border renders extra
+// pixels around the window so wide filters still get the samples splatted in
+// from outside its edges.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Crop {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+    pub border: usize,
+    // Output the full frame with everything outside the window black
+    // instead of just the window
+    pub full_frame: bool,
+}
+
+impl Crop {
+    pub fn new(x: usize, y: usize, width: usize, height: usize) -> Crop {
+        Crop {
+            x,
+            y,
+            width,
+            height,
+            border: 0,
+            full_frame: false,
+        }
+    }
+
+    // Columns and rows to sample, the window plus its border clipped to the image
+    pub(crate) fn render_region(
+        &self,
+        image_width: usize,
+        image_height: usize,
+    ) -> (Range<usize>, Range<usize>) {
+        (
+            self.x.saturating_sub(self.border)
+                ..(self.x + self.width + self.border).min(image_width),
+            self.y.saturating_sub(self.border)
+                ..(self.y + self.height + self.border).min(image_height),
+        )
+    }
+}
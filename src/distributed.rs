@@ -0,0 +1,454 @@
+use std::collections::VecDeque;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::ops::Range;
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::camera::Camera;
+use crate::checkpoint::{invalid_data, read_f64, read_string, read_u32, read_u64};
+use crate::checkpoint::{write_f64, write_string, write_u32, write_u64};
+use crate::crop::Crop;
+use crate::film::{Film, FilmTile};
+use crate::filter::{Filter, FilterKind};
+use crate::progress::{ProgressTracker, RenderObserver};
+use crate::sampler::SamplerKind;
+use crate::scene::Scene;
+
+const MAGIC: &[u8; 4] = b"RTDR";
+const VERSION: u32 = 2;
+const TILE_SIZE: usize = 32;
+
+const MESSAGE_DONE: u32 = 0;
+const MESSAGE_ASSIGN: u32 = 1;
+
+// Long enough for a slow worker to render a full tile
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(600);
+
+// Everything a render node needs to set up the same camera and world as the
+// coordinator. Samples are seeded per pixel and sample index, so any node
+// renders exactly the samples the coordinator would have.
+#[derive(Clone)]
+pub struct RenderJob {
+    pub width: usize,
+    pub seed: u64,
+    pub sampler: SamplerKind,
+    pub filter: Filter,
+    pub scene: Scene,
+    pub spectral: bool,
+    // Splits every tile into assignments of this many samples per pixel
+    pub samples_per_assignment: Option<i32>,
+    // A worker that takes longer than this to answer is given up on and its
+    // assignment handed to another
+    pub timeout: Duration,
+}
+
+impl RenderJob {
+    pub fn camera(&self) -> Camera {
+        let mut camera = Camera::new();
+        camera.set_image_width(self.width);
+        camera.set_seed(self.seed);
+        camera.set_sampler(self.sampler);
+        camera.set_filter(self.filter);
+        camera.set_spectral(self.spectral);
+        camera
+    }
+
+    fn assignments(&self, camera: &Camera) -> VecDeque<Assignment> {
+        let max_samples = camera.max_samples();
+        let chunk = self.samples_per_assignment.unwrap_or(max_samples).max(1);
+        let (width, height) = (camera.image_width(), camera.image_height());
+
+        let mut assignments = VecDeque::new();
+        for start in (0..max_samples).step_by(chunk as usize) {
+            for y in (0..height).step_by(TILE_SIZE) {
+                for x in (0..width).step_by(TILE_SIZE) {
+                    assignments.push_back(Assignment {
+                        tile: Crop::new(x, y, TILE_SIZE.min(width - x), TILE_SIZE.min(height - y)),
+                        samples: start..(start + chunk).min(max_samples),
+                    });
+                }
+            }
+        }
+        assignments
+    }
+
+    fn write(&self, out: &mut impl Write) -> io::Result<()> {
+        out.write_all(MAGIC)?;
+        write_u32(out, VERSION)?;
+        write_u32(out, self.width as u32)?;
+        write_u64(out, self.seed)?;
+        write_string(out, self.sampler.name())?;
+        write_string(out, self.filter.kind().name())?;
+        write_f64(out, self.filter.radius())?;
+        write_u32(out, self.spectral as u32)?;
+        write_string(out, &self.scene.to_text())
+    }
+
+    fn read(input: &mut impl Read) -> io::Result<RenderJob> {
+        let mut magic = [0; 4];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC || read_u32(input)? != VERSION {
+            return Err(invalid_data("not a render coordinator"));
+        }
+        let width = read_u32(input)? as usize;
+        let seed = read_u64(input)?;
+        let sampler = SamplerKind::from_name(&read_string(input)?)
+            .ok_or_else(|| invalid_data("unknown sampler"))?;
+        let filter_kind = FilterKind::from_name(&read_string(input)?)
+            .ok_or_else(|| invalid_data("unknown filter"))?;
+        let filter = Filter::with_radius(filter_kind, read_f64(input)?);
+        let spectral = read_u32(input)? != 0;
+        let scene = Scene::parse(&read_string(input)?)?;
+        Ok(RenderJob {
+            width,
+            seed,
+            sampler,
+            filter,
+            scene,
+            spectral,
+            samples_per_assignment: None,
+            timeout: DEFAULT_TIMEOUT,
+        })
+    }
+}
+
+// A tile and the range of sample indices to take in it
+#[derive(Clone)]
+struct Assignment {
+    tile: Crop,
+    samples: Range<i32>,
+}
+
+impl Assignment {
+    fn write(&self, out: &mut impl Write) -> io::Result<()> {
+        let tile = self.tile;
+        for v in [tile.x, tile.y, tile.width, tile.height] {
+            write_u32(out, v as u32)?;
+        }
+        write_u32(out, self.samples.start as u32)?;
+        write_u32(out, self.samples.end as u32)
+    }
+
+    fn read(input: &mut impl Read) -> io::Result<Assignment> {
+        let x = read_u32(input)? as usize;
+        let y = read_u32(input)? as usize;
+        let width = read_u32(input)? as usize;
+        let height = read_u32(input)? as usize;
+        let start = read_u32(input)? as i32;
+        let end = read_u32(input)? as i32;
+        Ok(Assignment {
+            tile: Crop::new(x, y, width, height),
+            samples: start..end,
+        })
+    }
+
+    fn pixel_samples(&self) -> u64 {
+        (self.tile.width * self.tile.height) as u64 * self.samples.len() as u64
+    }
+}
+
+// Listens for coordinators one at a time and renders whatever they assign.
+// A coordinator going away only ends that job, the worker keeps listening.
+pub fn run_worker(address: &str) -> io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    eprintln!("Worker listening on {}", listener.local_addr()?);
+    serve(listener);
+    Ok(())
+}
+
+fn serve(listener: TcpListener) {
+    for stream in listener.incoming() {
+        let result = stream.and_then(serve_coordinator);
+        if let Err(error) = result {
+            eprintln!("Coordinator connection failed: {}", error);
+        }
+    }
+}
+
+fn serve_coordinator(stream: TcpStream) -> io::Result<()> {
+    eprintln!("Rendering for {}", stream.peer_addr()?);
+    let mut input = BufReader::new(stream.try_clone()?);
+    let mut out = BufWriter::new(stream);
+    let job = RenderJob::read(&mut input)?;
+    let camera = job.camera();
+    let world = job.scene.build();
+
+    loop {
+        match read_u32(&mut input)? {
+            MESSAGE_ASSIGN => {
+                let assignment = Assignment::read(&mut input)?;
+                camera
+                    .render_tile(&world, assignment.tile, assignment.samples)
+                    .write(&mut out)?;
+                out.flush()?;
+            }
+            MESSAGE_DONE => return Ok(()),
+            _ => return Err(invalid_data("unknown message")),
+        }
+    }
+}
+
+// Assignments waiting for a worker, and how many are being rendered. Workers
+// keep waiting while others are busy since a failing worker hands its
+// assignment back.
+struct WorkQueue {
+    pending: VecDeque<Assignment>,
+    in_flight: usize,
+}
+
+struct SharedQueue {
+    queue: Mutex<WorkQueue>,
+    changed: Condvar,
+}
+
+impl SharedQueue {
+    fn next(&self) -> Option<Assignment> {
+        let mut queue = self.queue.lock().unwrap();
+        loop {
+            if let Some(assignment) = queue.pending.pop_front() {
+                queue.in_flight += 1;
+                return Some(assignment);
+            }
+            if queue.in_flight == 0 {
+                return None;
+            }
+            queue = self.changed.wait(queue).unwrap();
+        }
+    }
+
+    fn complete(&self) {
+        self.queue.lock().unwrap().in_flight -= 1;
+        self.changed.notify_all();
+    }
+
+    fn requeue(&self, assignment: Assignment) {
+        let mut queue = self.queue.lock().unwrap();
+        queue.pending.push_front(assignment);
+        queue.in_flight -= 1;
+        self.changed.notify_all();
+    }
+}
+
+// Splits the frame into tile and sample assignments, hands them out to the
+// workers and merges the returned tiles. When a worker disconnects, times out
+// or answers with the wrong tile its assignment goes to another one, if none
+// are left the coordinator renders the rest itself.
+pub fn run_coordinator(
+    job: &RenderJob,
+    workers: &[String],
+    observer: &mut dyn RenderObserver,
+) -> Film {
+    let camera = job.camera();
+    let assignments = job.assignments(&camera);
+    let total = assignments.len();
+    let shared = Arc::new(SharedQueue {
+        queue: Mutex::new(WorkQueue {
+            pending: assignments,
+            in_flight: 0,
+        }),
+        changed: Condvar::new(),
+    });
+
+    let (sender, receiver) = mpsc::channel();
+    for address in workers {
+        let (address, job) = (address.clone(), job.clone());
+        let (shared, sender) = (shared.clone(), sender.clone());
+        thread::spawn(move || drive_worker(&address, &job, &shared, sender));
+    }
+    drop(sender);
+
+    let mut film = Film::new(camera.image_width(), camera.image_height(), job.filter);
+    let mut tracker = ProgressTracker::new();
+    tracker.start(0.0);
+    let pixels = film.width() * film.height();
+    let mut merged = 0;
+    let mut report = |tracker: &mut ProgressTracker, merged: usize, samples: u64| {
+        tracker.samples += samples;
+        let fraction = merged as f64 / total as f64;
+        let pixels_done = (fraction * pixels as f64) as usize;
+        observer.on_progress(&tracker.progress(pixels_done, pixels, fraction));
+    };
+
+    for (assignment, tile) in receiver {
+        film.add_tile(&tile);
+        merged += 1;
+        report(&mut tracker, merged, assignment.pixel_samples());
+    }
+
+    // Every worker is gone, whatever is left is rendered here
+    let remaining: Vec<Assignment> = shared.queue.lock().unwrap().pending.drain(..).collect();
+    if !remaining.is_empty() {
+        eprintln!(
+            "No workers left, rendering {} assignments locally",
+            remaining.len()
+        );
+        let world = job.scene.build();
+        for assignment in remaining {
+            film.add_tile(&camera.render_tile(&world, assignment.tile, assignment.samples.clone()));
+            merged += 1;
+            report(&mut tracker, merged, assignment.pixel_samples());
+        }
+    }
+
+    observer.on_finish(&tracker.progress(pixels, pixels, 1.0));
+    film
+}
+
+fn drive_worker(
+    address: &str,
+    job: &RenderJob,
+    shared: &SharedQueue,
+    tiles: Sender<(Assignment, FilmTile)>,
+) {
+    let camera = job.camera();
+    let connection = TcpStream::connect(address).and_then(|stream| {
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(Some(job.timeout))?;
+        stream.set_write_timeout(Some(job.timeout))?;
+        let input = BufReader::new(stream.try_clone()?);
+        let mut out = BufWriter::new(stream);
+        job.write(&mut out)?;
+        out.flush()?;
+        Ok((input, out))
+    });
+    let (mut input, mut out) = match connection {
+        Ok(connection) => connection,
+        Err(error) => {
+            eprintln!("Could not connect to worker {}: {}", address, error);
+            return;
+        }
+    };
+
+    while let Some(assignment) = shared.next() {
+        let result = (|| {
+            write_u32(&mut out, MESSAGE_ASSIGN)?;
+            assignment.write(&mut out)?;
+            out.flush()?;
+            FilmTile::read(&mut input, camera.tile_bounds(assignment.tile))
+        })();
+        match result {
+            Ok(tile) => {
+                let sent = tiles.send((assignment, tile));
+                shared.complete();
+                if sent.is_err() {
+                    return;
+                }
+            }
+            Err(error) => {
+                eprintln!("Worker {} failed, reassigning its tile: {}", address, error);
+                shared.requeue(assignment);
+                return;
+            }
+        }
+    }
+
+    // The worker may already be gone, the job is finished either way
+    let _ = write_u32(&mut out, MESSAGE_DONE).and_then(|_| out.flush());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colour::Colour;
+    use crate::progress::Silent;
+    use crate::scene::MaterialDesc;
+    use crate::vec3::Point3;
+
+    fn job() -> RenderJob {
+        let mut scene = Scene::new();
+        let grey = MaterialDesc::Lambertian {
+            albedo: Colour::new(0.5, 0.5, 0.5),
+        };
+        scene.add_sphere(Point3::new(0.0, 1.0, 0.0), 1.5, grey);
+        // Two columns of tiles, the second narrower than the first
+        RenderJob {
+            width: 40,
+            seed: 11,
+            sampler: SamplerKind::Independent,
+            filter: Filter::new(FilterKind::Gaussian),
+            scene,
+            spectral: false,
+            samples_per_assignment: Some(25),
+            timeout: Duration::from_secs(2),
+        }
+    }
+
+    // Serves render jobs on a free localhost port
+    fn start_worker() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || serve(listener));
+        address
+    }
+
+    // Takes one assignment and then lets `fault` misbehave with it
+    fn start_faulty_worker(
+        taken: Sender<()>,
+        fault: impl FnOnce(BufWriter<TcpStream>, Assignment) + Send + 'static,
+    ) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut input = BufReader::new(stream.try_clone().unwrap());
+            RenderJob::read(&mut input).unwrap();
+            assert_eq!(read_u32(&mut input).unwrap(), MESSAGE_ASSIGN);
+            let assignment = Assignment::read(&mut input).unwrap();
+            taken.send(()).unwrap();
+            fault(BufWriter::new(stream), assignment);
+        });
+        address
+    }
+
+    // Tiles add up in a different order than samples do in a local render
+    fn assert_matches_local_render(job: &RenderJob, film: &Film) {
+        let local = job.camera().render(&job.scene.build(), &[]).image;
+        let image = film.to_framebuffer();
+        for y in 0..film.height() {
+            for x in 0..film.width() {
+                assert!((local.get(x, y) - image.get(x, y)).length() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn workers_render_the_local_image() {
+        let job = job();
+        let workers = [start_worker(), start_worker()];
+        let film = run_coordinator(&job, &workers, &mut Silent);
+        assert_matches_local_render(&job, &film);
+    }
+
+    #[test]
+    fn failed_assignments_are_reassigned() {
+        let job = job();
+        let (taken, faults) = mpsc::channel();
+        let disconnects = start_faulty_worker(taken.clone(), |_, _| {});
+        let hangs = start_faulty_worker(taken.clone(), |out, _| {
+            thread::sleep(Duration::from_secs(30));
+            drop(out);
+        });
+        let wrong_tile = start_faulty_worker(taken, |mut out, assignment| {
+            let tile = assignment.tile;
+            Film::new(40, 22, Filter::default())
+                .tile(tile.x + 1, tile.y, 8, 8)
+                .write(&mut out)
+                .unwrap();
+            out.flush().unwrap();
+        });
+        let workers = [
+            start_worker(),
+            start_worker(),
+            disconnects,
+            hangs,
+            wrong_tile,
+        ];
+
+        let film = run_coordinator(&job, &workers, &mut Silent);
+        assert_eq!(faults.try_iter().count(), 3);
+        assert_matches_local_render(&job, &film);
+    }
+}
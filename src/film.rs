@@ -0,0 +1,206 @@
+use std::io::{self, Read, Write};
+
+use crate::checkpoint::{invalid_data, read_f64, read_u32, read_vec3};
+use crate::checkpoint::{write_f64, write_u32, write_vec3};
+use crate::colour::Colour;
+use crate::crop::Crop;
+use crate::filter::Filter;
+use crate::framebuffer::Framebuffer;
+
+// Accumulates filter weighted sample sums per pixel. Positions are in raster
+// space with y pointing down, the centre of pixel (x, y) is at (x + 0.5, y + 0.5).
+// A film can cover just a window of the frame starting at (x, y), samples and
+// tiles are still placed in frame coordinates.
+#[derive(Clone)]
+pub struct Film {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+    filter: Filter,
+    sums: Vec<Colour>,
+    weights: Vec<f64>,
+}
+
+impl Film {
+    pub fn new(width: usize, height: usize, filter: Filter) -> Film {
+        Film::window(0, 0, width, height, filter)
+    }
+
+    pub fn window(x: usize, y: usize, width: usize, height: usize, filter: Filter) -> Film {
+        Film {
+            x,
+            y,
+            width,
+            height,
+            filter,
+            sums: vec![Colour::default(); width * height],
+            weights: vec![0.0; width * height],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    // Splat the sample into every pixel whose centre lies inside the filter radius
+    pub fn add_sample(&mut self, px: f64, py: f64, c: Colour) {
+        let radius = self.filter.radius();
+        let x0 = (px - 0.5 - radius).ceil().max(self.x as f64) as usize;
+        let y0 = (py - 0.5 - radius).ceil().max(self.y as f64) as usize;
+        let x1 = ((px - 0.5 + radius).floor() as isize).min((self.x + self.width) as isize - 1);
+        let y1 = ((py - 0.5 + radius).floor() as isize).min((self.y + self.height) as isize - 1);
+
+        for y in y0 as isize..=y1 {
+            for x in x0 as isize..=x1 {
+                let weight = self
+                    .filter
+                    .evaluate(x as f64 + 0.5 - px, y as f64 + 0.5 - py);
+                if weight == 0.0 {
+                    continue;
+                }
+                let i = (y as usize - self.y) * self.width + x as usize - self.x;
+                self.sums[i] += weight * c;
+                self.weights[i] += weight;
+            }
+        }
+    }
+
+    // Position of frame pixel (x, y) in buffers laid out like the film's window
+    pub(crate) fn index(&self, x: usize, y: usize) -> usize {
+        (y - self.y) * self.width + x - self.x
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> Colour {
+        let i = self.index(x, y);
+        // Negative lobed filters can leave a pixel without usable weight
+        if self.weights[i] <= 0.0 {
+            return Colour::default();
+        }
+        self.sums[i] / self.weights[i]
+    }
+
+    pub fn to_framebuffer(&self) -> Framebuffer {
+        let mut image = Framebuffer::new(self.width, self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                image.set(x, y, self.pixel(self.x + x, self.y + y));
+            }
+        }
+        image
+    }
+
+    // Copies out the accumulation of a rectangle, clipped to the film
+    pub fn tile(&self, x: usize, y: usize, width: usize, height: usize) -> FilmTile {
+        let width = width.min(self.x + self.width - x);
+        let height = height.min(self.y + self.height - y);
+        let mut tile = FilmTile {
+            x,
+            y,
+            width,
+            height,
+            sums: Vec::with_capacity(width * height),
+            weights: Vec::with_capacity(width * height),
+        };
+        for row in y..y + height {
+            let start = (row - self.y) * self.width + x - self.x;
+            tile.sums
+                .extend_from_slice(&self.sums[start..start + width]);
+            tile.weights
+                .extend_from_slice(&self.weights[start..start + width]);
+        }
+        tile
+    }
+
+    // Sums and weights add up, so tiles rendered separately merge exactly
+    // into the film a single render would have produced
+    pub fn add_tile(&mut self, tile: &FilmTile) {
+        assert!(
+            tile.x >= self.x
+                && tile.y >= self.y
+                && tile.x + tile.width <= self.x + self.width
+                && tile.y + tile.height <= self.y + self.height,
+            "Tile must lie inside the film"
+        );
+        for row in 0..tile.height {
+            for column in 0..tile.width {
+                let i = (tile.y + row - self.y) * self.width + tile.x + column - self.x;
+                self.sums[i] += tile.sums[row * tile.width + column];
+                self.weights[i] += tile.weights[row * tile.width + column];
+            }
+        }
+    }
+
+    pub(crate) fn write(&self, out: &mut impl Write) -> io::Result<()> {
+        write_u32(out, self.width as u32)?;
+        write_u32(out, self.height as u32)?;
+        for (sum, weight) in self.sums.iter().zip(&self.weights) {
+            write_vec3(out, *sum)?;
+            write_f64(out, *weight)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn read(input: &mut impl Read, filter: Filter) -> io::Result<Film> {
+        let width = read_u32(input)? as usize;
+        let height = read_u32(input)? as usize;
+        let mut film = Film::new(width, height, filter);
+        for i in 0..width * height {
+            film.sums[i] = read_vec3(input)?;
+            film.weights[i] = read_f64(input)?;
+        }
+        Ok(film)
+    }
+}
+
+// Accumulated sums and weights of a rectangle of a film
+pub struct FilmTile {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+    sums: Vec<Colour>,
+    weights: Vec<f64>,
+}
+
+impl FilmTile {
+    pub(crate) fn write(&self, out: &mut impl Write) -> io::Result<()> {
+        for v in [self.x, self.y, self.width, self.height] {
+            write_u32(out, v as u32)?;
+        }
+        for (sum, weight) in self.sums.iter().zip(&self.weights) {
+            write_vec3(out, *sum)?;
+            write_f64(out, *weight)?;
+        }
+        Ok(())
+    }
+
+    // Fails unless the tile covers exactly the expected rectangle, so a stale
+    // or corrupt reply never lands on the wrong pixels
+    pub(crate) fn read(input: &mut impl Read, expected: Crop) -> io::Result<FilmTile> {
+        let x = read_u32(input)? as usize;
+        let y = read_u32(input)? as usize;
+        let width = read_u32(input)? as usize;
+        let height = read_u32(input)? as usize;
+        if Crop::new(x, y, width, height) != expected {
+            return Err(invalid_data("tile does not match its assignment"));
+        }
+        let mut tile = FilmTile {
+            x,
+            y,
+            width,
+            height,
+            sums: Vec::with_capacity(width * height),
+            weights: Vec::with_capacity(width * height),
+        };
+        for _ in 0..width * height {
+            tile.sums.push(read_vec3(input)?);
+            tile.weights.push(read_f64(input)?);
+        }
+        Ok(tile)
+    }
+}
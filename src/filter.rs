@@ -0,0 +1,206 @@
+use crate::common::PI;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FilterKind {
+    Box,
+    Tent,
+    Gaussian,
+    Mitchell,
+    Lanczos,
+}
+
+impl FilterKind {
+    pub const ALL: [FilterKind; 5] = [
+        FilterKind::Box,
+        FilterKind::Tent,
+        FilterKind::Gaussian,
+        FilterKind::Mitchell,
+        FilterKind::Lanczos,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            FilterKind::Box => "box",
+            FilterKind::Tent => "tent",
+            FilterKind::Gaussian => "gaussian",
+            FilterKind::Mitchell => "mitchell",
+            FilterKind::Lanczos => "lanczos",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<FilterKind> {
+        FilterKind::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+
+    fn default_radius(self) -> f64 {
+        match self {
+            FilterKind::Box => 0.5,
+            FilterKind::Tent => 1.0,
+            FilterKind::Gaussian => 1.5,
+            FilterKind::Mitchell => 2.0,
+            FilterKind::Lanczos => 3.0,
+        }
+    }
+}
+
+// Separable pixel reconstruction filter, the radius is in pixels
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Filter {
+    kind: FilterKind,
+    radius: f64,
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Filter::new(FilterKind::Box)
+    }
+}
+
+impl Filter {
+    pub fn new(kind: FilterKind) -> Filter {
+        Filter {
+            kind,
+            radius: kind.default_radius(),
+        }
+    }
+
+    pub fn with_radius(kind: FilterKind, radius: f64) -> Filter {
+        assert!(
+            radius.is_finite() && radius > 0.0,
+            "Filter radius must be positive"
+        );
+        Filter { kind, radius }
+    }
+
+    pub fn kind(&self) -> FilterKind {
+        self.kind
+    }
+
+    pub fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    pub fn evaluate(&self, dx: f64, dy: f64) -> f64 {
+        self.evaluate_1d(dx) * self.evaluate_1d(dy)
+    }
+
+    fn evaluate_1d(&self, d: f64) -> f64 {
+        let d = d.abs();
+        if d > self.radius {
+            return 0.0;
+        }
+
+        match self.kind {
+            FilterKind::Box => 1.0,
+            FilterKind::Tent => self.radius - d,
+            FilterKind::Gaussian => {
+                const ALPHA: f64 = 2.0;
+                f64::exp(-ALPHA * d * d) - f64::exp(-ALPHA * self.radius * self.radius)
+            }
+            FilterKind::Mitchell => mitchell(2.0 * d / self.radius),
+            FilterKind::Lanczos => sinc(d) * sinc(d / self.radius),
+        }
+    }
+}
+
+// Mitchell-Netravali with B = C = 1/3, x in [0, 2]
+fn mitchell(x: f64) -> f64 {
+    const B: f64 = 1.0 / 3.0;
+    const C: f64 = 1.0 / 3.0;
+
+    let value = if x > 1.0 {
+        (-B - 6.0 * C) * x.powi(3)
+            + (6.0 * B + 30.0 * C) * x.powi(2)
+            + (-12.0 * B - 48.0 * C) * x
+            + (8.0 * B + 24.0 * C)
+    } else {
+        (12.0 - 9.0 * B - 6.0 * C) * x.powi(3)
+            + (-18.0 + 12.0 * B + 6.0 * C) * x.powi(2)
+            + (6.0 - 2.0 * B)
+    };
+    value / 6.0
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1.0e-5 {
+        return 1.0;
+    }
+    f64::sin(PI * x) / (PI * x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colour::Colour;
+    use crate::film::Film;
+
+    // Midpoint rule over the filter's support
+    fn integral(filter: &Filter) -> f64 {
+        const STEPS: usize = 200;
+        let step = 2.0 * filter.radius() / STEPS as f64;
+        let mut total = 0.0;
+        for j in 0..STEPS {
+            for i in 0..STEPS {
+                let dx = -filter.radius() + (i as f64 + 0.5) * step;
+                let dy = -filter.radius() + (j as f64 + 0.5) * step;
+                total += filter.evaluate(dx, dy) * step * step;
+            }
+        }
+        total
+    }
+
+    #[test]
+    #[should_panic(expected = "Filter radius must be positive")]
+    fn zero_radius_is_rejected() {
+        Filter::with_radius(FilterKind::Gaussian, 0.0);
+    }
+
+    #[test]
+    fn weights_integrate_over_support() {
+        for kind in FilterKind::ALL {
+            for filter in [Filter::new(kind), Filter::with_radius(kind, 2.5)] {
+                let r = filter.radius();
+                let total = integral(&filter);
+                assert!(
+                    total.is_finite() && total > 0.0,
+                    "{} integrates to {}",
+                    kind.name(),
+                    total
+                );
+                assert!(filter.evaluate(0.0, 0.0) > 0.0);
+                assert_eq!(filter.evaluate(1.01 * r, 0.0), 0.0);
+                assert_eq!(filter.evaluate(0.0, -1.01 * r), 0.0);
+            }
+        }
+    }
+
+    // Film divides each pixel's weighted sum by its total weight, so a
+    // constant image comes back unchanged whatever the filter's scale
+    #[test]
+    fn constant_image_is_preserved() {
+        let colour = Colour::new(0.25, 0.5, 0.75);
+        for kind in FilterKind::ALL {
+            let mut film = Film::new(8, 6, Filter::new(kind));
+            let per_pixel = 4;
+            for j in 0..6 * per_pixel {
+                for i in 0..8 * per_pixel {
+                    let px = (i as f64 + 0.5) / per_pixel as f64;
+                    let py = (j as f64 + 0.5) / per_pixel as f64;
+                    film.add_sample(px, py, colour);
+                }
+            }
+            for y in 0..6 {
+                for x in 0..8 {
+                    let difference = film.pixel(x, y) - colour;
+                    assert!(
+                        difference.length() < 1e-9,
+                        "{} at ({}, {})",
+                        kind.name(),
+                        x,
+                        y
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use std::io::Write;
+
+use crate::colour::{self, Colour};
+
+// Row-major pixel storage, row 0 is the top of the image
+#[derive(Clone)]
+pub struct Framebuffer {
+    width: usize,
+    height: usize,
+    pixels: Vec<Colour>,
+}
+
+impl Framebuffer {
+    pub fn new(width: usize, height: usize) -> Framebuffer {
+        Framebuffer {
+            width,
+            height,
+            pixels: vec![Colour::default(); width * height],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Colour {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, c: Colour) {
+        self.pixels[y * self.width + x] = c;
+    }
+
+    // Gamma corrected 8-bit output, pixels are expected to be averaged already
+    pub fn write_ppm(&self, out: &mut impl Write) {
+        writeln!(out, "P3\n{} {}\n255", self.width, self.height).expect("Writing header");
+        for c in &self.pixels {
+            colour::write_colour(out, *c, 1);
+        }
+    }
+
+    // Linear 32-bit float output for compositing, PFM stores rows bottom to top
+    pub fn write_pfm(&self, out: &mut impl Write) {
+        write!(out, "PF\n{} {}\n-1.0\n", self.width, self.height).expect("Writing header");
+        for row in self.pixels.chunks(self.width).rev() {
+            for c in row {
+                for v in [c.x(), c.y(), c.z()] {
+                    out.write_all(&(v as f32).to_le_bytes())
+                        .expect("Writing colour");
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Just enough of a PFM reader to check what write_pfm produced
+    fn read_pfm(bytes: &[u8]) -> Framebuffer {
+        let mut header = bytes.splitn(4, |&b| b == b'\n');
+        assert_eq!(header.next().unwrap(), b"PF");
+        let size = String::from_utf8(header.next().unwrap().to_vec()).unwrap();
+        let (width, height) = size.split_once(' ').unwrap();
+        let (width, height) = (width.parse().unwrap(), height.parse().unwrap());
+        assert_eq!(header.next().unwrap(), b"-1.0");
+        let data = header.next().unwrap();
+        assert_eq!(data.len(), width * height * 12);
+
+        let values: Vec<f64> = data
+            .chunks(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()) as f64)
+            .collect();
+        let mut image = Framebuffer::new(width, height);
+        for (i, c) in values.chunks(3).enumerate() {
+            image.set(
+                i % width,
+                height - 1 - i / width,
+                Colour::new(c[0], c[1], c[2]),
+            );
+        }
+        image
+    }
+
+    #[test]
+    fn pfm_round_trips() {
+        let mut image = Framebuffer::new(5, 3);
+        for y in 0..3 {
+            for x in 0..5 {
+                let v = (y * 5 + x) as f64;
+                image.set(x, y, Colour::new(v * 0.25, -v, 1.0e6 + v));
+            }
+        }
+        let mut bytes = Vec::new();
+        image.write_pfm(&mut bytes);
+
+        let read = read_pfm(&bytes);
+        assert_eq!((read.width(), read.height()), (5, 3));
+        for y in 0..3 {
+            for x in 0..5 {
+                assert_eq!(read.get(x, y), image.get(x, y));
+            }
+        }
+    }
+}
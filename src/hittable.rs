@@ -2,6 +2,7 @@ use std::rc::Rc;
 
 use crate::material::Material;
 use crate::ray::Ray;
+use crate::stats::{self, Primitive};
 use crate::vec3::{Point3, Vec3};
 
 #[derive(Default, Clone)]
@@ -11,6 +12,12 @@ pub struct HitRecord {
     pub mat: Option<Rc<dyn Material>>,
     pub t: f64,
     pub front_face: bool,
+    // Direction of increasing u on the surface, not normalised. Anisotropic
+    // materials orient their roughness along it. Zero where the shape has no
+    // well defined direction.
+    pub tangent: Vec3,
+    // Index of the top level object in the world list
+    pub object_id: usize,
 }
 
 impl HitRecord {
@@ -49,14 +56,16 @@ impl HittableList {
 
 impl Hittable for HittableList {
     fn hit(&self, ray: &Ray, ray_tmin: f64, ray_tmax: f64, rec: &mut HitRecord) -> bool {
+        stats::count_hit(Primitive::List);
         let mut temp_rec = HitRecord::new();
         let mut hit_anything = false;
         let mut closest_so_far = ray_tmax;
 
-        for object in &self.objects {
+        for (id, object) in self.objects.iter().enumerate() {
             if object.hit(ray, ray_tmin, closest_so_far, &mut temp_rec) {
                 hit_anything = true;
                 closest_so_far = temp_rec.t;
+                temp_rec.object_id = id;
                 *rec = temp_rec.clone();
             }
         }
@@ -1,8 +1,30 @@
+pub mod accumulation;
+pub mod adaptive;
+pub mod aov;
 pub mod camera;
+pub mod cancel;
+pub mod checkpoint;
 pub mod colour;
 pub mod common;
+pub mod crop;
+pub mod denoise;
+pub mod distributed;
+pub mod film;
+pub mod filter;
+pub mod framebuffer;
 pub mod hittable;
 pub mod material;
+pub mod medium;
+pub mod microfacet;
+#[cfg(feature = "preview")]
+pub mod preview;
+pub mod progress;
 pub mod ray;
+pub mod sampler;
+pub mod scene;
+pub mod spectrum;
 pub mod sphere;
+pub mod stats;
+pub mod terminal;
+pub mod thin_film;
 pub mod vec3;
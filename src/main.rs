@@ -1,23 +1,342 @@
-use std::rc::Rc;
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
 
+use ray_tracing::accumulation::Accumulation;
+use ray_tracing::adaptive::AdaptiveSampling;
+use ray_tracing::aov::Aov;
 use ray_tracing::camera::Camera;
+use ray_tracing::cancel::{CancelToken, StopReason};
+use ray_tracing::checkpoint::RenderState;
 use ray_tracing::colour::{self, Colour};
-use ray_tracing::common::{random_double, random_double_range};
+use ray_tracing::common::{self, random_double, random_double_range};
+use ray_tracing::crop::Crop;
+use ray_tracing::denoise::Denoiser;
+use ray_tracing::distributed::{self, RenderJob};
+use ray_tracing::film::Film;
+use ray_tracing::filter::{Filter, FilterKind};
 use ray_tracing::hittable::*;
-use ray_tracing::material::{Dialectric, Lambertian, Metal};
-use ray_tracing::sphere::Sphere;
+use ray_tracing::progress::{JsonReporter, Progress, ProgressBar, RenderObserver, Silent};
+use ray_tracing::sampler::SamplerKind;
+use ray_tracing::scene::{MaterialDesc, Scene};
+use ray_tracing::terminal::{self, ColourMode};
 use ray_tracing::vec3;
 use ray_tracing::vec3::*;
 
-fn main() {
-    let mut world = HittableList::new();
+#[derive(Default)]
+struct Options {
+    aovs: Vec<Aov>,
+    denoise: bool,
+    adaptive_threshold: Option<f64>,
+    min_samples: Option<i32>,
+    max_samples: Option<i32>,
+    sampler: Option<SamplerKind>,
+    filter: Option<FilterKind>,
+    filter_radius: Option<f64>,
+    samples_per_pass: Option<i32>,
+    snapshot: Option<String>,
+    snapshot_interval: Option<f64>,
+    seed: Option<u64>,
+    scene_seed: Option<u64>,
+    checkpoint: Option<String>,
+    checkpoint_interval: Option<f64>,
+    resume: bool,
+    preview: bool,
+    width: Option<usize>,
+    terminal: bool,
+    ansi256: bool,
+    progress: Option<String>,
+    time_budget: Option<f64>,
+    stats: Option<String>,
+    crop: Option<Crop>,
+    crop_border: Option<usize>,
+    crop_full_frame: bool,
+    scene: Option<String>,
+    save_scene: Option<String>,
+    worker: Option<String>,
+    render_nodes: Vec<String>,
+    worker_timeout: Option<f64>,
+    accumulation: Option<String>,
+    spectral: bool,
+}
+
+impl Options {
+    fn parse() -> Options {
+        let mut options = Options::default();
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--aov" => {
+                    let name: String = next_value(&mut args, &arg);
+                    if name == "all" {
+                        options.aovs.extend(Aov::ALL);
+                    } else {
+                        options
+                            .aovs
+                            .push(Aov::from_name(&name).expect("Unknown AOV"));
+                    }
+                }
+                "--denoise" => options.denoise = true,
+                "--adaptive" => options.adaptive_threshold = Some(next_value(&mut args, &arg)),
+                "--min-samples" => options.min_samples = Some(next_value(&mut args, &arg)),
+                "--max-samples" => options.max_samples = Some(next_value(&mut args, &arg)),
+                "--sampler" => {
+                    let name: String = next_value(&mut args, &arg);
+                    options.sampler = Some(SamplerKind::from_name(&name).expect("Unknown sampler"));
+                }
+                "--filter" => {
+                    let name: String = next_value(&mut args, &arg);
+                    options.filter = Some(FilterKind::from_name(&name).expect("Unknown filter"));
+                }
+                "--filter-radius" => options.filter_radius = Some(next_value(&mut args, &arg)),
+                "--progressive" => options.samples_per_pass = Some(next_value(&mut args, &arg)),
+                "--snapshot" => options.snapshot = Some(next_value(&mut args, &arg)),
+                "--snapshot-interval" => {
+                    options.snapshot_interval = Some(next_value(&mut args, &arg))
+                }
+                "--seed" => options.seed = Some(next_value(&mut args, &arg)),
+                "--scene-seed" => options.scene_seed = Some(next_value(&mut args, &arg)),
+                "--checkpoint" => options.checkpoint = Some(next_value(&mut args, &arg)),
+                "--checkpoint-interval" => {
+                    options.checkpoint_interval = Some(next_value(&mut args, &arg))
+                }
+                "--resume" => options.resume = true,
+                "--preview" => options.preview = true,
+                "--width" => options.width = Some(next_value(&mut args, &arg)),
+                "--terminal" => options.terminal = true,
+                "--spectral" => options.spectral = true,
+                "--ansi256" => options.ansi256 = true,
+                "--progress" => options.progress = Some(next_value(&mut args, &arg)),
+                "--time-budget" => options.time_budget = Some(next_value(&mut args, &arg)),
+                "--stats" => options.stats = Some(next_value(&mut args, &arg)),
+                "--crop" => {
+                    let window: String = next_value(&mut args, &arg);
+                    let values = window
+                        .split(',')
+                        .map(|v| v.parse().expect("--crop needs x,y,width,height"))
+                        .collect::<Vec<usize>>();
+                    let [x, y, width, height] = values[..] else {
+                        panic!("--crop needs x,y,width,height");
+                    };
+                    options.crop = Some(Crop::new(x, y, width, height));
+                }
+                "--scene" => options.scene = Some(next_value(&mut args, &arg)),
+                "--save-scene" => options.save_scene = Some(next_value(&mut args, &arg)),
+                "--accumulation" => options.accumulation = Some(next_value(&mut args, &arg)),
+                "--worker" => options.worker = Some(next_value(&mut args, &arg)),
+                "--render-nodes" => {
+                    let nodes: String = next_value(&mut args, &arg);
+                    options
+                        .render_nodes
+                        .extend(nodes.split(',').map(String::from));
+                }
+                "--worker-timeout" => options.worker_timeout = Some(next_value(&mut args, &arg)),
+                "--crop-border" => options.crop_border = Some(next_value(&mut args, &arg)),
+                "--crop-full-frame" => options.crop_full_frame = true,
+                _ => panic!("Unknown argument {}", arg),
+            }
+        }
+        if let Some(crop) = &mut options.crop {
+            crop.border = options.crop_border.unwrap_or(0);
+            crop.full_frame = options.crop_full_frame;
+        } else if options.crop_border.is_some() || options.crop_full_frame {
+            usage_error("--crop-border and --crop-full-frame need --crop");
+        }
+        if options
+            .filter_radius
+            .is_some_and(|radius| !radius.is_finite() || radius <= 0.0)
+        {
+            usage_error("--filter-radius must be a positive number of pixels");
+        }
+        options
+    }
+
+    fn filter(&self) -> Filter {
+        let kind = self.filter.unwrap_or(FilterKind::Box);
+        match self.filter_radius {
+            Some(radius) => Filter::with_radius(kind, radius),
+            None => Filter::new(kind),
+        }
+    }
+
+    fn reporter(&self) -> Box<dyn RenderObserver> {
+        match self.progress.as_deref() {
+            None | Some("bar") => Box::new(ProgressBar::new()),
+            Some("json") => Box::new(JsonReporter::new(io::stderr(), Duration::from_secs(1))),
+            Some("silent") => Box::new(Silent),
+            Some(mode) => panic!("Unknown progress mode {}", mode),
+        }
+    }
+
+    fn adaptive_sampling(&self) -> Option<AdaptiveSampling> {
+        let mut adaptive = AdaptiveSampling::new(self.adaptive_threshold?);
+        if let Some(n) = self.min_samples {
+            adaptive.min_samples = n;
+        }
+        if let Some(n) = self.max_samples {
+            adaptive.max_samples = n;
+        }
+        if adaptive.threshold.is_nan() || adaptive.threshold <= 0.0 {
+            usage_error("--adaptive needs a positive threshold");
+        }
+        if adaptive.min_samples < 1 || adaptive.min_samples > adaptive.max_samples {
+            usage_error("--min-samples must be at least 1 and at most --max-samples");
+        }
+        Some(adaptive)
+    }
+}
+
+// Invalid combinations of otherwise valid arguments
+fn usage_error(message: &str) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(2);
+}
+
+fn next_value<T: FromStr>(args: &mut impl Iterator<Item = String>, flag: &str) -> T {
+    args.next()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or_else(|| panic!("{} needs a valid value", flag))
+}
+
+// Renders with an explicit --seed or an accumulation file share this random
+// scene unless --scene-seed asks for another one, so they can be merged
+const DEFAULT_SCENE_SEED: u64 = 0;
 
-    let material_ground = Rc::new(Lambertian::new(Colour::new(0.5, 0.5, 0.5)));
-    world.add(Box::new(Sphere::new(
-        Point3::new(0.0, -1000.0, 0.0),
-        1000.0,
-        material_ground,
-    )));
+// Checkpointing needs pass boundaries to save at
+const CHECKPOINT_SAMPLES_PER_PASS: i32 = 8;
+
+// Fires on the first call and then once `interval` seconds have passed,
+// or on every call without an interval
+struct Periodic {
+    interval: Option<f64>,
+    last: Option<Instant>,
+}
+
+impl Periodic {
+    fn new(interval: Option<f64>) -> Periodic {
+        Periodic {
+            interval,
+            last: None,
+        }
+    }
+
+    fn due(&mut self) -> bool {
+        let due = match (self.interval, self.last) {
+            (Some(interval), Some(last)) => last.elapsed().as_secs_f64() >= interval,
+            _ => true,
+        };
+        if due {
+            self.last = Some(Instant::now());
+        }
+        due
+    }
+}
+
+// Forwards progress to the chosen reporter and writes snapshots and
+// checkpoints as passes finish. Snapshots with an interval are also written
+// part way through a pass, so long passes still update them.
+struct CliObserver<'a> {
+    options: &'a Options,
+    reporter: Box<dyn RenderObserver>,
+    snapshots: Periodic,
+    checkpoints: Periodic,
+}
+
+impl CliObserver<'_> {
+    fn new(options: &Options) -> CliObserver<'_> {
+        CliObserver {
+            options,
+            reporter: options.reporter(),
+            snapshots: Periodic::new(options.snapshot_interval),
+            checkpoints: Periodic::new(options.checkpoint_interval),
+        }
+    }
+}
+
+impl RenderObserver for CliObserver<'_> {
+    fn on_progress(&mut self, progress: &Progress) {
+        self.reporter.on_progress(progress);
+    }
+
+    fn on_row(&mut self, film: &Film) {
+        if let (Some(path), Some(_)) = (&self.options.snapshot, self.options.snapshot_interval) {
+            if self.snapshots.due() {
+                write_snapshot(path, film);
+            }
+        }
+    }
+
+    fn on_pass(&mut self, state: &RenderState) {
+        if let Some(path) = &self.options.snapshot {
+            if self.snapshots.due() {
+                write_snapshot(path, state.film());
+            }
+        }
+        if let Some(path) = &self.options.checkpoint {
+            if self.checkpoints.due() {
+                state.save(path).expect("Writing checkpoint");
+            }
+        }
+    }
+
+    fn on_finish(&mut self, progress: &Progress) {
+        self.reporter.on_finish(progress);
+    }
+}
+
+// Written to a temporary file first so viewers never see a half written image
+fn write_snapshot(path: &str, film: &Film) {
+    let temp_path = format!("{}.tmp", path);
+    let file = File::create(&temp_path).expect("Creating snapshot file");
+    film.to_framebuffer().write_ppm(&mut BufWriter::new(file));
+    fs::rename(&temp_path, path).expect("Replacing snapshot file");
+}
+
+#[cfg(feature = "preview")]
+fn preview(cam: &mut Camera, world: &HittableList, options: &Options) {
+    // Single sample passes keep the window responsive
+    cam.set_progressive(Some(options.samples_per_pass.unwrap_or(1)));
+    let save_path = options.snapshot.as_deref().unwrap_or("preview.ppm");
+    ray_tracing::preview::run(cam, world, save_path);
+}
+
+#[cfg(not(feature = "preview"))]
+fn preview(_cam: &mut Camera, _world: &HittableList, _options: &Options) {
+    panic!("--preview needs the binary built with the preview feature");
+}
+
+// The terminal preview fits the terminal width unless --width is given
+fn terminal_columns() -> usize {
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.parse().ok())
+        .unwrap_or(80)
+}
+
+// Every character row holds two pixel rows
+fn terminal_preview(cam: &mut Camera, world: &HittableList, options: &Options) {
+    cam.set_progressive(Some(options.samples_per_pass.unwrap_or(1)));
+    let mode = if options.ansi256 {
+        ColourMode::Ansi256
+    } else {
+        ColourMode::detect()
+    };
+
+    let mut stdout = io::stdout();
+    terminal::clear(&mut stdout);
+    cam.render_progressive(world, &[], None, &mut |state: &RenderState| {
+        terminal::draw(&mut stdout, state.film(), mode)
+    });
+}
+
+fn random_scene() -> Scene {
+    let mut scene = Scene::new();
+
+    let material_ground = MaterialDesc::Lambertian {
+        albedo: Colour::new(0.5, 0.5, 0.5),
+    };
+    scene.add_sphere(Point3::new(0.0, -1000.0, 0.0), 1000.0, material_ground);
 
     for a in -11..11 {
         for b in -11..11 {
@@ -32,46 +351,336 @@ fn main() {
                 if choose_mat < 0.8 {
                     // diffuse
                     let albedo = colour::random() * colour::random();
-                    let material = Rc::new(Lambertian::new(albedo));
+                    let material = MaterialDesc::Lambertian { albedo };
                     // add movement with a second centre point at t = 1
                     let centre2 = centre + Point3::new(0.0, random_double_range(0.0, 0.5), 0.0);
-                    world.add(Box::new(Sphere::new_moving(centre, centre2, 0.2, material)));
+                    scene.add_moving_sphere(centre, centre2, 0.2, material);
                 } else if choose_mat < 0.95 {
                     // metal
                     let albedo = vec3::random_range(0.5, 1.0);
-                    let fuzz = random_double_range(0.0, 0.5);
-                    let material = Rc::new(Metal::new(albedo, fuzz));
-                    world.add(Box::new(Sphere::new(centre, 0.2, material)));
+                    let roughness = random_double_range(0.0, 0.5);
+                    let material = MaterialDesc::Metal {
+                        reflectance: albedo,
+                        roughness,
+                        film: None,
+                    };
+                    scene.add_sphere(centre, 0.2, material);
                 } else {
                     // glass
-                    let material = Rc::new(Dialectric::new(1.5));
-                    world.add(Box::new(Sphere::new(centre, 0.2, material)));
+                    let material = MaterialDesc::Dielectric {
+                        refraction_index: 1.5,
+                        roughness: 0.0,
+                        absorption: Colour::default(),
+                        dispersion: None,
+                        film: None,
+                    };
+                    scene.add_sphere(centre, 0.2, material);
                 }
             }
         }
     }
 
-    let material1 = Rc::new(Dialectric::new(1.5));
-    world.add(Box::new(Sphere::new(
-        Point3::new(0.0, 1.0, 0.0),
-        1.0,
-        material1,
-    )));
+    let material1 = MaterialDesc::Dielectric {
+        refraction_index: 1.5,
+        roughness: 0.0,
+        absorption: Colour::default(),
+        dispersion: None,
+        film: None,
+    };
+    scene.add_sphere(Point3::new(0.0, 1.0, 0.0), 1.0, material1);
+
+    let material2 = MaterialDesc::Lambertian {
+        albedo: Colour::new(0.4, 0.2, 0.1),
+    };
+    scene.add_sphere(Point3::new(-4.0, 1.0, 0.0), 1.0, material2);
 
-    let material2 = Rc::new(Lambertian::new(Colour::new(0.4, 0.2, 0.1)));
-    world.add(Box::new(Sphere::new(
-        Point3::new(-4.0, 1.0, 0.0),
-        1.0,
-        material2,
-    )));
+    let material3 = MaterialDesc::Metal {
+        reflectance: Colour::new(0.7, 0.6, 0.5),
+        roughness: 0.0,
+        film: None,
+    };
+    scene.add_sphere(Point3::new(4.0, 1.0, 0.0), 1.0, material3);
+
+    scene
+}
 
-    let material3 = Rc::new(Metal::new(Colour::new(0.7, 0.6, 0.5), 0.0));
-    world.add(Box::new(Sphere::new(
-        Point3::new(4.0, 1.0, 0.0),
-        1.0,
-        material3,
-    )));
+// `merge [--output FILE] FILE...` adds up accumulations written with
+// --accumulation and writes the combined image to stdout, optionally also as
+// an accumulation for further merging
+fn merge(mut args: impl Iterator<Item = String>) {
+    let mut output: Option<String> = None;
+    let mut merged: Option<Accumulation> = None;
+    while let Some(arg) = args.next() {
+        if arg == "--output" {
+            output = Some(next_value(&mut args, &arg));
+            continue;
+        }
+        let accumulation =
+            Accumulation::load(&arg).unwrap_or_else(|e| panic!("Loading {}: {}", arg, e));
+        match &mut merged {
+            Some(merged) => merged.merge(&accumulation),
+            None => merged = Some(accumulation),
+        }
+    }
 
-    let cam = Camera::new();
-    cam.render(&world);
+    let merged = merged.expect("merge needs at least one accumulation file");
+    if let Some(path) = output {
+        merged.save(&path).expect("Writing merged accumulation");
+    }
+    merged
+        .to_framebuffer()
+        .write_ppm(&mut BufWriter::new(std::io::stdout()));
+}
+
+fn main() {
+    if std::env::args().nth(1).as_deref() == Some("merge") {
+        merge(std::env::args().skip(2));
+        return;
+    }
+    let options = Options::parse();
+
+    if let Some(address) = &options.worker {
+        distributed::run_worker(address).expect("Running render worker");
+        return;
+    }
+
+    let resume = options.resume.then(|| {
+        let path = options
+            .checkpoint
+            .as_ref()
+            .expect("--resume needs --checkpoint");
+        RenderState::load(path).expect("Loading checkpoint")
+    });
+    let seed = match &resume {
+        Some(state) => state.seed(),
+        None => options.seed.unwrap_or_else(rand::random),
+    };
+    // Otherwise every run gets a new random scene, generated from the sample
+    // seed so a resumed render rebuilds it
+    let scene_seed = match options.scene_seed {
+        Some(scene_seed) => scene_seed,
+        None if options.seed.is_some() || options.accumulation.is_some() => DEFAULT_SCENE_SEED,
+        None => seed,
+    };
+    common::seed_random(scene_seed);
+    let scene = match &options.scene {
+        Some(path) => Scene::load(path).expect("Loading scene"),
+        None => random_scene(),
+    };
+    if let Some(path) = &options.save_scene {
+        fs::write(path, scene.to_text()).expect("Writing scene");
+    }
+    let world = scene.build();
+
+    // The denoiser is guided by albedo and normals, render them even if not written out
+    let mut render_aovs = options.aovs.clone();
+    if options.denoise {
+        render_aovs.extend([Aov::Albedo, Aov::Normal]);
+    }
+
+    let mut cam = Camera::new();
+    // The width has to be final before the crop window is checked against it
+    let width = options
+        .width
+        .or_else(|| options.terminal.then(terminal_columns));
+    if let Some(width) = width {
+        cam.set_image_width(width);
+    }
+    cam.set_crop(options.crop);
+    cam.set_adaptive_sampling(options.adaptive_sampling());
+    cam.set_filter(options.filter());
+    if let Some(sampler) = options.sampler {
+        cam.set_sampler(sampler);
+    }
+    cam.set_seed(seed);
+    cam.set_scene_fingerprint(scene.fingerprint());
+    cam.set_spectral(options.spectral);
+    cam.set_count_hits(options.stats.is_some());
+    cam.set_time_budget(options.time_budget.map(Duration::from_secs_f64));
+    cam.set_progressive(
+        options.samples_per_pass.or(options
+            .checkpoint
+            .as_ref()
+            .map(|_| CHECKPOINT_SAMPLES_PER_PASS)),
+    );
+
+    if options.preview {
+        preview(&mut cam, &world, &options);
+        return;
+    }
+    if options.terminal {
+        terminal_preview(&mut cam, &world, &options);
+        return;
+    }
+
+    if !options.render_nodes.is_empty() {
+        assert!(
+            render_aovs.is_empty() && options.adaptive_threshold.is_none(),
+            "AOVs, denoising and adaptive sampling are not supported with --render-nodes"
+        );
+        let job = RenderJob {
+            width: cam.image_width(),
+            seed,
+            sampler: options.sampler.unwrap_or(SamplerKind::Independent),
+            filter: options.filter(),
+            scene,
+            spectral: options.spectral,
+            samples_per_assignment: options.samples_per_pass,
+            timeout: options
+                .worker_timeout
+                .map_or(distributed::DEFAULT_TIMEOUT, Duration::from_secs_f64),
+        };
+        let film =
+            distributed::run_coordinator(&job, &options.render_nodes, &mut *options.reporter());
+        film.to_framebuffer()
+            .write_ppm(&mut BufWriter::new(std::io::stdout()));
+        return;
+    }
+
+    // The first Ctrl-C stops the render and keeps what it has, a second one
+    // quits straight away
+    let cancel = CancelToken::new();
+    cam.set_cancel_token(cancel.clone());
+    ctrlc::set_handler(move || {
+        if cancel.is_cancelled() {
+            std::process::exit(130);
+        }
+        cancel.cancel();
+    })
+    .expect("Installing Ctrl-C handler");
+
+    let output = cam.render_progressive(
+        &world,
+        &render_aovs,
+        resume,
+        &mut CliObserver::new(&options),
+    );
+    match output.stop_reason {
+        StopReason::Complete => {}
+        StopReason::Cancelled => eprintln!("Render cancelled, writing the partial image"),
+        StopReason::TimeBudget => eprintln!("Time budget reached, writing the partial image"),
+    }
+    if let Some(path) = &options.accumulation {
+        output
+            .accumulation
+            .save(path)
+            .expect("Writing accumulation");
+    }
+    match options.stats.as_deref() {
+        None => {}
+        Some("summary") => output.stats.write_summary(&mut io::stderr()),
+        Some("json") => output.stats.write_json(&mut io::stderr()),
+        Some(mode) => panic!("Unknown stats format {}", mode),
+    }
+
+    let image = if options.denoise {
+        Denoiser::new().denoise(
+            &output.image,
+            output.aovs.get(Aov::Albedo),
+            output.aovs.get(Aov::Normal),
+        )
+    } else {
+        output.image
+    };
+
+    image.write_ppm(&mut BufWriter::new(std::io::stdout()));
+    // Each AOV is written next to the beauty image as a float PFM
+    for (aov, buffer) in output
+        .aovs
+        .iter()
+        .filter(|(aov, _)| options.aovs.contains(aov))
+    {
+        let file = File::create(format!("{}.pfm", aov.name())).expect("Creating AOV file");
+        buffer.write_pfm(&mut BufWriter::new(file));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use ray_tracing::material::Lambertian;
+    use ray_tracing::sphere::Sphere;
+
+    #[test]
+    fn periodic_is_due_once_per_interval() {
+        let mut always = Periodic::new(None);
+        assert!(always.due());
+        assert!(always.due());
+
+        let mut periodic = Periodic::new(Some(0.05));
+        assert!(periodic.due());
+        assert!(!periodic.due());
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(periodic.due());
+        assert!(!periodic.due());
+    }
+
+    // Reads the snapshot back after every pass the CLI observer has seen
+    struct SnapshotReader<'a> {
+        observer: CliObserver<'a>,
+        passes: Vec<(Vec<u8>, Vec<u8>)>,
+    }
+
+    impl RenderObserver for SnapshotReader<'_> {
+        fn on_row(&mut self, film: &Film) {
+            self.observer.on_row(film);
+        }
+
+        fn on_pass(&mut self, state: &RenderState) {
+            self.observer.on_pass(state);
+            let mut image = Vec::new();
+            state.film().to_framebuffer().write_ppm(&mut image);
+            let path = self.observer.options.snapshot.as_ref().unwrap();
+            self.passes
+                .push((fs::read(path).expect("Reading snapshot"), image));
+        }
+    }
+
+    fn snapshot_passes(name: &str, interval: Option<f64>) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let path = std::env::temp_dir().join(format!("{}-{}.ppm", name, std::process::id()));
+        let options = Options {
+            snapshot: Some(path.to_str().unwrap().to_string()),
+            snapshot_interval: interval,
+            progress: Some(String::from("silent")),
+            ..Default::default()
+        };
+        let mut world = HittableList::new();
+        world.add(Box::new(Sphere::new(
+            Point3::new(0.0, 0.0, -1.0),
+            0.5,
+            Rc::new(Lambertian::new(Colour::new(0.5, 0.5, 0.5))),
+        )));
+        let mut cam = Camera::new();
+        cam.set_image_width(16);
+        cam.set_progressive(Some(25));
+        let mut reader = SnapshotReader {
+            observer: CliObserver::new(&options),
+            passes: Vec::new(),
+        };
+        cam.render_progressive(&world, &[], None, &mut reader);
+        fs::remove_file(&path).expect("Removing snapshot");
+        reader.passes
+    }
+
+    #[test]
+    fn snapshot_is_written_after_every_pass() {
+        let passes = snapshot_passes("snapshot-every-pass", None);
+        assert_eq!(passes.len(), 4);
+        for (snapshot, image) in &passes {
+            assert!(snapshot == image);
+        }
+    }
+
+    #[test]
+    fn snapshot_interval_limits_writes() {
+        // Only the first row of the first pass falls due within the interval
+        let passes = snapshot_passes("snapshot-interval", Some(1000.0));
+        assert_eq!(passes.len(), 4);
+        for (snapshot, image) in &passes {
+            assert!(snapshot == &passes[0].0);
+            assert!(snapshot != image);
+        }
+    }
 }
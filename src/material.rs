@@ -1,17 +1,65 @@
-use crate::colour::Colour;
-use crate::common::random_double;
+use crate::colour::{self, Colour};
+use crate::common::PI;
 use crate::hittable::HitRecord;
+use crate::medium::Medium;
+use crate::microfacet::{self, fresnel_conductor, fresnel_dielectric, fresnel_schlick, Frame, Ggx};
 use crate::ray::Ray;
-use crate::vec3;
+use crate::sampler::Sampler;
+use crate::spectrum::{self, Dispersion};
+use crate::thin_film::ThinFilm;
+use crate::vec3::{self, Vec3};
+
+// What a material needs to know about the path besides the hit itself,
+// filled in by the path tracer for every scattering event
+#[derive(Clone, Copy, Debug)]
+pub struct PathState {
+    // Refractive index on the outside of the surface, from the media the
+    // path is in
+    pub exterior_ior: f64,
+    // Hero wavelength in nanometres when rendering spectrally
+    pub wavelength: Option<f64>,
+}
+
+impl Default for PathState {
+    fn default() -> Self {
+        PathState::new()
+    }
+}
+
+impl PathState {
+    pub fn new() -> PathState {
+        PathState {
+            exterior_ior: 1.0,
+            wavelength: None,
+        }
+    }
+}
 
 pub trait Material {
     fn scatter(
         &self,
         r_in: &Ray,
         rec: &HitRecord,
+        path: &PathState,
         attenuation: &mut Colour,
         scattered: &mut Ray,
+        sampler: &mut dyn Sampler,
     ) -> bool;
+
+    // Surface colour used for the albedo AOV
+    fn albedo(&self, _rec: &HitRecord) -> Colour {
+        Colour::new(1.0, 1.0, 1.0)
+    }
+
+    // What a path travels through after refracting into the object
+    fn medium(&self) -> Option<Medium> {
+        None
+    }
+
+    // Whether scattering depends on the wavelength in spectral mode
+    fn is_dispersive(&self) -> bool {
+        false
+    }
 }
 
 pub struct Lambertian {
@@ -29,10 +77,12 @@ impl Material for Lambertian {
         &self,
         r_in: &Ray,
         rec: &HitRecord,
+        _path: &PathState,
         attenuation: &mut Colour,
         scattered: &mut Ray,
+        sampler: &mut dyn Sampler,
     ) -> bool {
-        let mut scatter_direction = rec.normal + vec3::random_unit_vector();
+        let mut scatter_direction = rec.normal + vec3::sample_unit_vector(sampler.get_2d());
 
         // if scatter is degenerate (zero) return some default
         if scatter_direction.near_zero() {
@@ -44,58 +94,250 @@ impl Material for Lambertian {
 
         true
     }
+
+    fn albedo(&self, _rec: &HitRecord) -> Colour {
+        self.albedo
+    }
 }
 
-pub struct Metal {
+// Rough diffuse surface after Oren and Nayar 1994, qualitative model. Sigma
+// is the standard deviation of the facet slope angle in radians, zero is
+// Lambertian. Rough surfaces look flatter and brighter towards the light.
+// The model is not energy conserving: seen at grazing angles with a sigma
+// below about 0.33 it reflects up to about 1% more than the albedo.
+pub struct OrenNayar {
     albedo: Colour,
-    fuzz: f64,
+    a: f64,
+    b: f64,
+}
+
+impl OrenNayar {
+    pub fn new(albedo: Colour, sigma: f64) -> OrenNayar {
+        let sigma2 = sigma * sigma;
+        let a = 1.0 - 0.5 * sigma2 / (sigma2 + 0.33);
+        let b = 0.45 * sigma2 / (sigma2 + 0.09);
+        OrenNayar { albedo, a, b }
+    }
+
+    // The BRDF over albedo / pi, for local directions
+    fn factor(&self, wo: Vec3, wi: Vec3) -> f64 {
+        // cos(phi_i - phi_o) sin(theta_i) sin(theta_o), zero at the pole
+        let cos_phi_sines = (wi.x() * wo.x() + wi.y() * wo.y()).max(0.0);
+        // sin(alpha) tan(beta) with alpha the larger of the two angles
+        let sin_alpha_tan_beta = if wi.z().abs() > wo.z().abs() {
+            cos_phi_sines / wi.z().abs().max(1e-8)
+        } else {
+            cos_phi_sines / wo.z().abs().max(1e-8)
+        };
+        self.a + self.b * sin_alpha_tan_beta
+    }
+}
+
+impl Material for OrenNayar {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        _path: &PathState,
+        attenuation: &mut Colour,
+        scattered: &mut Ray,
+        sampler: &mut dyn Sampler,
+    ) -> bool {
+        let frame = Frame::from_normal(rec.normal);
+        let wo = frame.to_local(-vec3::unit_vector(r_in.direction));
+        let mut wi = Vec3::new(0.0, 0.0, 1.0) + vec3::sample_unit_vector(sampler.get_2d());
+        if wi.near_zero() {
+            wi = Vec3::new(0.0, 0.0, 1.0);
+        }
+        let wi = vec3::unit_vector(wi);
+
+        // Cosine sampling cancels the cosine and 1/pi of the BRDF
+        *attenuation = self.albedo * self.factor(wo, wi);
+        *scattered = Ray::new_tm(rec.p, frame.to_world(wi), r_in.tm);
+        true
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Colour {
+        self.albedo
+    }
 }
 
-impl Metal {
-    pub fn new(a: Colour, fuzz: f64) -> Metal {
-        Metal {
-            albedo: a,
-            fuzz: if fuzz < 1.0 { fuzz } else { 1.0 },
+// Complex refractive indices (eta, k) at roughly 650, 550 and 450nm
+const CONDUCTOR_PRESETS: [(&str, [f64; 3], [f64; 3]); 4] = [
+    ("gold", [0.143, 0.374, 1.442], [3.983, 2.385, 1.603]),
+    ("copper", [0.200, 0.924, 1.102], [3.912, 2.452, 2.142]),
+    ("aluminium", [1.657, 0.880, 0.521], [9.224, 6.270, 4.837]),
+    ("silver", [0.155, 0.117, 0.138], [4.828, 3.122, 2.147]),
+];
+
+// GGX microfacet metal. Sampling visible normals leaves Fresnel times the
+// ratio of shadowing-masking to masking as the sample weight. Light that
+// would bounce below the surface is lost since there is no multiple
+// scattering between microfacets.
+pub struct Conductor {
+    eta: Colour,
+    k: Colour,
+    distribution: Ggx,
+    film: Option<ThinFilm>,
+}
+
+impl Conductor {
+    pub fn new(eta: Colour, k: Colour, roughness: f64) -> Conductor {
+        Conductor::anisotropic(eta, k, roughness, roughness)
+    }
+
+    // `roughness_x` applies along the surface tangent, which runs around the
+    // vertical axis on a sphere, and `roughness_y` across it
+    pub fn anisotropic(eta: Colour, k: Colour, roughness_x: f64, roughness_y: f64) -> Conductor {
+        Conductor {
+            eta,
+            k,
+            distribution: Ggx::from_roughness(roughness_x, roughness_y),
+            film: None,
         }
     }
+
+    pub fn set_thin_film(&mut self, film: Option<ThinFilm>) {
+        self.film = film;
+    }
+
+    // The film sits between the conductor and whatever the path is in
+    fn fresnel(&self, cos_theta: f64, path: &PathState) -> Colour {
+        match (self.film, path.wavelength) {
+            (None, _) => fresnel_conductor(cos_theta, self.eta, self.k),
+            (Some(film), None) => {
+                film.reflectance_rgb(cos_theta, path.exterior_ior, (self.eta, self.k))
+            }
+            (Some(film), Some(lambda)) => {
+                let eta = spectrum::rgb_at_wavelength(self.eta, lambda);
+                let k = spectrum::rgb_at_wavelength(self.k, lambda);
+                let r = film.reflectance(cos_theta, path.exterior_ior, (eta, k), lambda);
+                Colour::new(r, r, r)
+            }
+        }
+    }
+
+    // The index with eta = 1 whose reflectance at normal incidence is `reflectance`
+    pub fn from_reflectance(reflectance: Colour, roughness: f64) -> Conductor {
+        let k = |r: f64| {
+            let r = r.clamp(0.0, 0.9999);
+            2.0 * (r / (1.0 - r)).sqrt()
+        };
+        let k = Colour::new(k(reflectance.x()), k(reflectance.y()), k(reflectance.z()));
+        Conductor::new(Colour::new(1.0, 1.0, 1.0), k, roughness)
+    }
+
+    // Index of gold, copper, aluminium or silver
+    pub fn preset_ior(name: &str) -> Option<(Colour, Colour)> {
+        CONDUCTOR_PRESETS
+            .iter()
+            .find(|(preset, _, _)| *preset == name)
+            .map(|(_, [er, eg, eb], [kr, kg, kb])| {
+                (Colour::new(*er, *eg, *eb), Colour::new(*kr, *kg, *kb))
+            })
+    }
 }
 
-impl Material for Metal {
+impl Material for Conductor {
     fn scatter(
         &self,
         r_in: &Ray,
         rec: &HitRecord,
+        path: &PathState,
         attenuation: &mut Colour,
         scattered: &mut Ray,
+        sampler: &mut dyn Sampler,
     ) -> bool {
-        let reflected = vec3::reflect(vec3::unit_vector(r_in.direction), rec.normal);
+        let frame = Frame::from_tangent(rec.normal, rec.tangent);
+        let wo = frame.to_local(-vec3::unit_vector(r_in.direction));
+        if wo.z() <= 0.0 {
+            return false;
+        }
 
-        *attenuation = self.albedo;
-        *scattered = Ray::new_tm(
-            rec.p,
-            reflected + self.fuzz * vec3::random_in_unit_sphere(),
-            r_in.tm,
-        );
-        scattered.direction.dot(&rec.normal) > 0.0
+        let wi = if self.distribution.is_smooth() {
+            *attenuation = self.fresnel(wo.z(), path);
+            Vec3::new(-wo.x(), -wo.y(), wo.z())
+        } else {
+            let m = self
+                .distribution
+                .sample_visible_normal(wo, sampler.get_2d());
+            let wi = vec3::reflect(-wo, m);
+            if wi.z() <= 0.0 {
+                return false;
+            }
+            *attenuation = self.fresnel(wo.dot(&m), path)
+                * (self.distribution.g2(wo, wi) / self.distribution.g1(wo));
+            wi
+        };
+
+        *scattered = Ray::new_tm(rec.p, frame.to_world(wi), r_in.tm);
+        true
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Colour {
+        fresnel_conductor(1.0, self.eta, self.k)
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.film.is_some()
     }
 }
 
+// Absorption coefficient per unit distance that leaves `transmittance` of
+// the light after travelling `distance` through a medium
+pub fn absorption_from_transmittance(transmittance: Colour, distance: f64) -> Colour {
+    let coefficient = |t: f64| -t.max(1.0e-6).ln() / distance;
+    Colour::new(
+        coefficient(transmittance.x()),
+        coefficient(transmittance.y()),
+        coefficient(transmittance.z()),
+    )
+}
+
 pub struct Dialectric {
-    // Relative refractive index compared to surrounding medium
+    // Refractive index of the inside, the outside comes from the hit record
     refractive_index: f64,
+    // Beer-Lambert coefficient of the medium inside, zero for clear glass
+    absorption: Colour,
+    // Replaces the fixed index at the hero wavelength in spectral mode
+    dispersion: Option<Dispersion>,
+    film: Option<ThinFilm>,
 }
 
 impl Dialectric {
     pub fn new(r: f64) -> Dialectric {
+        Dialectric::absorbing(r, Colour::default())
+    }
+
+    pub fn absorbing(r: f64, absorption: Colour) -> Dialectric {
         Dialectric {
             refractive_index: r,
+            absorption,
+            dispersion: None,
+            film: None,
         }
     }
 
-    fn reflectance(cosine: f64, refractive_index: f64) -> f64 {
-        // Use Schlick approximation
-        let r0 = ((1.0 - refractive_index) / (1.0 + refractive_index)).powi(2);
-        r0 + (1.0 - r0) * ((1.0 - cosine).powi(5))
+    // Outside spectral mode it behaves like glass with the index at the
+    // sodium D line
+    pub fn dispersive(dispersion: Dispersion, absorption: Colour) -> Dialectric {
+        Dialectric {
+            refractive_index: dispersion.refractive_index(spectrum::LAMBDA_D),
+            absorption,
+            dispersion: Some(dispersion),
+            film: None,
+        }
+    }
+
+    pub fn set_thin_film(&mut self, film: Option<ThinFilm>) {
+        self.film = film;
+    }
+
+    fn refractive_index_at(&self, wavelength: Option<f64>) -> f64 {
+        match (self.dispersion, wavelength) {
+            (Some(dispersion), Some(lambda)) => dispersion.refractive_index(lambda),
+            _ => self.refractive_index,
+        }
     }
 }
 
@@ -104,30 +346,1043 @@ impl Material for Dialectric {
         &self,
         r_in: &Ray,
         rec: &HitRecord,
+        path: &PathState,
         attenuation: &mut Colour,
         scattered: &mut Ray,
+        sampler: &mut dyn Sampler,
     ) -> bool {
         *attenuation = Colour::new(1.0, 1.0, 1.0);
+        // Index on the incoming side over the index on the far side
+        let interior_ior = self.refractive_index_at(path.wavelength);
         let refractive_index = if rec.front_face {
-            1.0 / self.refractive_index
+            path.exterior_ior / interior_ior
         } else {
-            self.refractive_index
+            interior_ior / path.exterior_ior
         };
         let unit_direction = vec3::unit_vector(r_in.direction);
         let cos_theta = -unit_direction.dot(&rec.normal).min(1.0);
-        let sin_theta = f64::sqrt(1.0 - cos_theta.powi(2));
+        let choice = sampler.get_1d();
 
-        // No solution to Snell's law in the below case, ray must reflect instead
-        let cannnot_refract = (refractive_index * sin_theta) > 1.0
-            || Self::reflectance(cos_theta, refractive_index) > random_double();
+        let Some(film) = self.film else {
+            let reflectance = fresnel_dielectric(cos_theta, 1.0 / refractive_index);
+            // Total internal reflection leaves no refracted direction
+            let result_ray = vec3::refract(unit_direction, rec.normal, refractive_index)
+                .filter(|_| choice >= reflectance)
+                .unwrap_or_else(|| vec3::reflect(unit_direction, rec.normal));
+            *scattered = Ray::new_tm(rec.p, result_ray, r_in.tm);
+            return true;
+        };
 
-        let result_ray = if cannnot_refract {
-            vec3::reflect(unit_direction, rec.normal)
+        // A coloured reflectance is sampled by its average and the choice
+        // weighted back, the film does not change the refracted direction
+        let (incoming_ior, far_ior) = if rec.front_face {
+            (path.exterior_ior, interior_ior)
         } else {
-            vec3::refract(unit_direction, rec.normal, refractive_index)
+            (interior_ior, path.exterior_ior)
+        };
+        let reflectance = match path.wavelength {
+            Some(lambda) => {
+                let r = film.reflectance(cos_theta, incoming_ior, (far_ior, 0.0), lambda);
+                Colour::new(r, r, r)
+            }
+            None => film.reflectance_rgb(
+                cos_theta,
+                incoming_ior,
+                (Colour::new(far_ior, far_ior, far_ior), Colour::default()),
+            ),
+        };
+        let probability = (reflectance.x() + reflectance.y() + reflectance.z()) / 3.0;
+        let refracted = vec3::refract(unit_direction, rec.normal, refractive_index);
+        let result_ray = match refracted {
+            Some(direction) if choice >= probability => {
+                *attenuation = (Colour::new(1.0, 1.0, 1.0) - reflectance) / (1.0 - probability);
+                direction
+            }
+            Some(_) => {
+                *attenuation = reflectance / probability;
+                vec3::reflect(unit_direction, rec.normal)
+            }
+            None => vec3::reflect(unit_direction, rec.normal),
         };
 
         *scattered = Ray::new_tm(rec.p, result_ray, r_in.tm);
         true
     }
+
+    fn medium(&self) -> Option<Medium> {
+        Some(Medium::absorbing(self.refractive_index, self.absorption))
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.dispersion.is_some() || self.film.is_some()
+    }
+}
+
+// Translucent material such as skin, wax, marble or milk. Behind a smooth
+// dielectric surface light random walks through the object, scattering off
+// particles until it finds its way out again. The albedo is the colour the
+// object appears, the mean free path per channel how far light of that
+// colour travels between scattering events.
+pub struct Subsurface {
+    albedo: Colour,
+    mean_free_path: Colour,
+    anisotropy: f64,
+    interface: Dialectric,
+}
+
+impl Subsurface {
+    pub fn new(
+        albedo: Colour,
+        mean_free_path: Colour,
+        anisotropy: f64,
+        refractive_index: f64,
+    ) -> Subsurface {
+        assert!(
+            mean_free_path.x() > 0.0 && mean_free_path.y() > 0.0 && mean_free_path.z() > 0.0,
+            "Mean free path must be positive"
+        );
+        Subsurface {
+            albedo,
+            mean_free_path,
+            anisotropy,
+            interface: Dialectric::new(refractive_index),
+        }
+    }
+}
+
+// Single scattering albedo that makes a random walk come out with the given
+// multiple scattering albedo, fit by Chiang et al. 2016
+fn single_scattering_albedo(albedo: f64) -> f64 {
+    let a = albedo.clamp(0.0, 0.999);
+    let s = 4.09712 + 4.20863 * a - (9.59217 + 41.6808 * a + 17.7126 * a * a).sqrt();
+    1.0 - s * s
+}
+
+impl Material for Subsurface {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        path: &PathState,
+        attenuation: &mut Colour,
+        scattered: &mut Ray,
+        sampler: &mut dyn Sampler,
+    ) -> bool {
+        self.interface
+            .scatter(r_in, rec, path, attenuation, scattered, sampler)
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Colour {
+        self.albedo
+    }
+
+    fn medium(&self) -> Option<Medium> {
+        let channel = |albedo: f64, mean_free_path: f64| {
+            let extinction = 1.0 / mean_free_path;
+            let scattering = single_scattering_albedo(albedo) * extinction;
+            (extinction - scattering, scattering)
+        };
+        let (ax, sx) = channel(self.albedo.x(), self.mean_free_path.x());
+        let (ay, sy) = channel(self.albedo.y(), self.mean_free_path.y());
+        let (az, sz) = channel(self.albedo.z(), self.mean_free_path.z());
+        Some(Medium {
+            refractive_index: self.interface.refractive_index,
+            absorption: Colour::new(ax, ay, az),
+            scattering: Colour::new(sx, sy, sz),
+            anisotropy: self.anisotropy,
+        })
+    }
+}
+
+// GGX microfacet glass after Walter et al. 2007, reflecting or refracting
+// through a sampled visible microfacet in proportion to its Fresnel
+// reflectance so only shadowing-masking is left in the weight
+pub struct RoughDielectric {
+    refractive_index: f64,
+    distribution: Ggx,
+    absorption: Colour,
+}
+
+impl RoughDielectric {
+    pub fn new(refractive_index: f64, roughness: f64) -> RoughDielectric {
+        RoughDielectric::absorbing(refractive_index, roughness, Colour::default())
+    }
+
+    pub fn absorbing(refractive_index: f64, roughness: f64, absorption: Colour) -> RoughDielectric {
+        RoughDielectric {
+            refractive_index,
+            distribution: Ggx::from_roughness(roughness, roughness),
+            absorption,
+        }
+    }
+}
+
+impl RoughDielectric {
+    // Density over solid angle of the local direction `wi` that scatter picks
+    // for `wo` on a rough surface, `eta` as for `fresnel_dielectric`. Through
+    // the half vector it is the visible normal density times the chance of
+    // reflecting or refracting, times the Jacobian of the half vector.
+    pub fn pdf(&self, wo: Vec3, wi: Vec3, eta: f64) -> f64 {
+        let reflected = wi.z() > 0.0;
+        let m = if reflected { wo + wi } else { wo + eta * wi };
+        if m.near_zero() {
+            return 0.0;
+        }
+        let m = vec3::unit_vector(if m.z() < 0.0 { -m } else { m });
+        let (cos_o, cos_i) = (wo.dot(&m), wi.dot(&m));
+        if cos_o <= 0.0 || (!reflected && cos_i >= 0.0) {
+            return 0.0;
+        }
+
+        let density = self.distribution.visible_normal_pdf(wo, m);
+        let fresnel = fresnel_dielectric(cos_o, eta);
+        if reflected {
+            fresnel * density / (4.0 * cos_o)
+        } else {
+            let denominator = cos_o + eta * cos_i;
+            (1.0 - fresnel) * density * eta * eta * -cos_i / (denominator * denominator)
+        }
+    }
+}
+
+impl Material for RoughDielectric {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        path: &PathState,
+        attenuation: &mut Colour,
+        scattered: &mut Ray,
+        sampler: &mut dyn Sampler,
+    ) -> bool {
+        // The hit normal faces the incoming ray, so the far side is inside
+        // when entering
+        let eta = if rec.front_face {
+            self.refractive_index / path.exterior_ior
+        } else {
+            path.exterior_ior / self.refractive_index
+        };
+        let frame = Frame::from_normal(rec.normal);
+        let wo = frame.to_local(-vec3::unit_vector(r_in.direction));
+        if wo.z() <= 0.0 {
+            return false;
+        }
+
+        let choice = sampler.get_1d();
+        let m = if self.distribution.is_smooth() {
+            Vec3::new(0.0, 0.0, 1.0)
+        } else {
+            self.distribution
+                .sample_visible_normal(wo, sampler.get_2d())
+        };
+
+        let transmitted = microfacet::refract(wo, m, eta)
+            .filter(|_| choice >= fresnel_dielectric(wo.dot(&m), eta));
+        let wi = match transmitted {
+            Some(wi) if wi.z() < 0.0 => wi,
+            Some(_) => return false,
+            None => {
+                let wi = vec3::reflect(-wo, m);
+                if wi.z() <= 0.0 {
+                    return false;
+                }
+                wi
+            }
+        };
+
+        let weight = if self.distribution.is_smooth() {
+            1.0
+        } else {
+            self.distribution.g2(wo, wi) / self.distribution.g1(wo)
+        };
+        *attenuation = Colour::new(weight, weight, weight);
+        *scattered = Ray::new_tm(rec.p, frame.to_world(wi), r_in.tm);
+        true
+    }
+
+    fn medium(&self) -> Option<Medium> {
+        Some(Medium::absorbing(self.refractive_index, self.absorption))
+    }
+}
+
+// Diffuse base under a clear dielectric coat, like plastic or varnished
+// wood. Light reaching the base has passed the coat twice, and what the coat
+// reflects back down bounces off the base again, summed as a geometric
+// series over the coat's average internal reflectance. Sampling picks the
+// coat in proportion to its Fresnel reflectance and the base otherwise.
+pub struct Coated {
+    albedo: Colour,
+    refractive_index: f64,
+    distribution: Ggx,
+    internal_reflectance: f64,
+}
+
+impl Coated {
+    pub fn new(albedo: Colour, refractive_index: f64, roughness: f64) -> Coated {
+        Coated {
+            albedo,
+            refractive_index,
+            distribution: Ggx::from_roughness(roughness, roughness),
+            internal_reflectance: microfacet::fresnel_diffuse_reflectance(1.0 / refractive_index),
+        }
+    }
+}
+
+impl Material for Coated {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        _path: &PathState,
+        attenuation: &mut Colour,
+        scattered: &mut Ray,
+        sampler: &mut dyn Sampler,
+    ) -> bool {
+        let frame = Frame::from_normal(rec.normal);
+        let wo = frame.to_local(-vec3::unit_vector(r_in.direction));
+        if wo.z() <= 0.0 {
+            return false;
+        }
+
+        let eta = self.refractive_index;
+        let fresnel_out = fresnel_dielectric(wo.z(), eta);
+        let diffuse_weight = colour::luminance(self.albedo) * (1.0 - fresnel_out);
+        if fresnel_out + diffuse_weight <= 0.0 {
+            return false;
+        }
+        let coat_probability = fresnel_out / (fresnel_out + diffuse_weight);
+
+        let wi = if sampler.get_1d() < coat_probability {
+            let m = if self.distribution.is_smooth() {
+                Vec3::new(0.0, 0.0, 1.0)
+            } else {
+                self.distribution
+                    .sample_visible_normal(wo, sampler.get_2d())
+            };
+            let wi = vec3::reflect(-wo, m);
+            if wi.z() <= 0.0 {
+                return false;
+            }
+            let mut weight = fresnel_dielectric(wo.dot(&m), eta) / coat_probability;
+            if !self.distribution.is_smooth() {
+                weight *= self.distribution.g2(wo, wi) / self.distribution.g1(wo);
+            }
+            *attenuation = Colour::new(weight, weight, weight);
+            wi
+        } else {
+            let mut wi = Vec3::new(0.0, 0.0, 1.0) + vec3::sample_unit_vector(sampler.get_2d());
+            if wi.near_zero() {
+                wi = Vec3::new(0.0, 0.0, 1.0);
+            }
+            let wi = vec3::unit_vector(wi);
+            let fresnel_in = fresnel_dielectric(wi.z(), eta);
+            // Light refracted into the coat is spread over a smaller solid
+            // angle, hence the 1 / eta^2
+            let multiple_bounces = Colour::new(
+                self.albedo.x() / (1.0 - self.albedo.x() * self.internal_reflectance),
+                self.albedo.y() / (1.0 - self.albedo.y() * self.internal_reflectance),
+                self.albedo.z() / (1.0 - self.albedo.z() * self.internal_reflectance),
+            );
+            let transmitted = (1.0 - fresnel_in) * (1.0 - fresnel_out) / (eta * eta);
+            *attenuation = multiple_bounces * (transmitted / (1.0 - coat_probability));
+            wi
+        };
+
+        *scattered = Ray::new_tm(rec.p, frame.to_world(wi), r_in.tm);
+        true
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Colour {
+        self.albedo
+    }
+}
+
+// Disney principled BSDF (Burley 2012, 2015). Each lobe is sampled on its
+// own, picked in proportion to a rough estimate of how much it reflects, and
+// the sample is weighted by the lobe's share over the probability of picking
+// it. Transmission goes through the same rough glass as `RoughDielectric`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Principled {
+    pub base_colour: Colour,
+    pub metallic: f64,
+    pub roughness: f64,
+    pub specular: f64,
+    pub specular_tint: f64,
+    pub sheen: f64,
+    pub clearcoat: f64,
+    pub clearcoat_gloss: f64,
+    pub transmission: f64,
+    pub refractive_index: f64,
+}
+
+// Disney's default sheen tint, which is not exposed
+const SHEEN_TINT: f64 = 0.5;
+
+#[derive(Clone, Copy)]
+enum Lobe {
+    Diffuse,
+    Specular,
+    Clearcoat,
+    Transmission,
+}
+
+impl Principled {
+    // A plastic-like dielectric with the defaults from the Disney paper
+    pub fn new(base_colour: Colour) -> Principled {
+        Principled {
+            base_colour,
+            metallic: 0.0,
+            roughness: 0.5,
+            specular: 0.5,
+            specular_tint: 0.0,
+            sheen: 0.0,
+            clearcoat: 0.0,
+            clearcoat_gloss: 1.0,
+            transmission: 0.0,
+            refractive_index: 1.5,
+        }
+    }
+
+    // Base colour normalised to unit luminance, the hue without brightness
+    fn tint(&self) -> Colour {
+        let luminance = colour::luminance(self.base_colour);
+        if luminance > 0.0 {
+            self.base_colour / luminance
+        } else {
+            Colour::new(1.0, 1.0, 1.0)
+        }
+    }
+
+    fn specular_colour(&self) -> Colour {
+        let white = Colour::new(1.0, 1.0, 1.0);
+        let dielectric = 0.08 * self.specular * lerp(white, self.tint(), self.specular_tint);
+        lerp(dielectric, self.base_colour, self.metallic)
+    }
+
+    // Share of each lobe in the BSDF and the probability of sampling it
+    fn lobes(&self, cos_theta_o: f64) -> [(Lobe, f64, f64); 4] {
+        let dielectric = 1.0 - self.metallic;
+        let specular_reflectance =
+            colour::luminance(fresnel_schlick(cos_theta_o, self.specular_colour()));
+        let clearcoat_reflectance = fresnel_schlick(cos_theta_o, Colour::new(0.04, 0.04, 0.04));
+        // Lobe, its share and roughly how much of that share it reflects
+        let lobes = [
+            (
+                Lobe::Diffuse,
+                dielectric * (1.0 - self.transmission),
+                colour::luminance(self.base_colour) + self.sheen,
+            ),
+            (
+                Lobe::Specular,
+                1.0 - dielectric * self.transmission,
+                specular_reflectance,
+            ),
+            (
+                Lobe::Clearcoat,
+                0.25 * self.clearcoat,
+                clearcoat_reflectance.x(),
+            ),
+            (Lobe::Transmission, dielectric * self.transmission, 1.0),
+        ];
+        let total: f64 = lobes.iter().map(|(_, share, albedo)| share * albedo).sum();
+        lobes.map(|(lobe, share, albedo)| {
+            let probability = if total > 0.0 {
+                share * albedo / total
+            } else {
+                0.0
+            };
+            (lobe, share, probability)
+        })
+    }
+
+    // Burley's diffuse with grazing retro-reflection, plus sheen
+    fn sample_diffuse(&self, wo: Vec3, u: (f64, f64)) -> Option<(Vec3, Colour)> {
+        let mut wi = Vec3::new(0.0, 0.0, 1.0) + vec3::sample_unit_vector(u);
+        if wi.near_zero() {
+            wi = Vec3::new(0.0, 0.0, 1.0);
+        }
+        let wi = vec3::unit_vector(wi);
+        let cos_theta_d = wi.dot(&vec3::unit_vector(wi + wo));
+        let schlick_weight = |cos: f64| (1.0 - cos.clamp(0.0, 1.0)).powi(5);
+        let fd90 = 0.5 + 2.0 * self.roughness * cos_theta_d * cos_theta_d;
+        let fd = (1.0 + (fd90 - 1.0) * schlick_weight(wi.z()))
+            * (1.0 + (fd90 - 1.0) * schlick_weight(wo.z()));
+        let sheen_colour = lerp(Colour::new(1.0, 1.0, 1.0), self.tint(), SHEEN_TINT);
+        // Cosine sampling cancels the cosine and 1/pi of the diffuse term
+        let weight =
+            fd * self.base_colour + PI * self.sheen * schlick_weight(cos_theta_d) * sheen_colour;
+        Some((wi, weight))
+    }
+
+    fn sample_specular(&self, wo: Vec3, u: (f64, f64)) -> Option<(Vec3, Colour)> {
+        let distribution = Ggx::from_roughness(self.roughness, self.roughness);
+        let m = if distribution.is_smooth() {
+            Vec3::new(0.0, 0.0, 1.0)
+        } else {
+            distribution.sample_visible_normal(wo, u)
+        };
+        let wi = vec3::reflect(-wo, m);
+        if wi.z() <= 0.0 {
+            return None;
+        }
+        let fresnel = fresnel_schlick(wi.dot(&m), self.specular_colour());
+        if distribution.is_smooth() {
+            Some((wi, fresnel))
+        } else {
+            Some((
+                wi,
+                fresnel * (distribution.g2(wo, wi) / distribution.g1(wo)),
+            ))
+        }
+    }
+
+    // Fixed index of 1.5 and GGX masking with alpha 0.25, as in the paper
+    fn sample_clearcoat(&self, wo: Vec3, u: (f64, f64)) -> Option<(Vec3, Colour)> {
+        let alpha = lerp_f64(0.1, 0.001, self.clearcoat_gloss);
+        let m = microfacet::sample_gtr1(alpha, u);
+        let wi = vec3::reflect(-wo, m);
+        if wi.z() <= 0.0 || wo.dot(&m) <= 0.0 {
+            return None;
+        }
+        let masking = Ggx::from_roughness(0.5, 0.5);
+        let g = masking.g1(wo) * masking.g1(wi);
+        // The distribution cancels against the sampling density
+        let fresnel = fresnel_schlick(wi.dot(&m), Colour::new(0.04, 0.04, 0.04));
+        Some((wi, fresnel * (g * wo.dot(&m) / (wo.z() * m.z()))))
+    }
+}
+
+fn lerp(a: Colour, b: Colour, t: f64) -> Colour {
+    (1.0 - t) * a + t * b
+}
+
+fn lerp_f64(a: f64, b: f64, t: f64) -> f64 {
+    (1.0 - t) * a + t * b
+}
+
+impl Material for Principled {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        path: &PathState,
+        attenuation: &mut Colour,
+        scattered: &mut Ray,
+        sampler: &mut dyn Sampler,
+    ) -> bool {
+        let glass = RoughDielectric::new(self.refractive_index, self.roughness);
+        // Inside a transmissive object only the glass interface is left
+        if !rec.front_face && self.transmission > 0.0 {
+            return glass.scatter(r_in, rec, path, attenuation, scattered, sampler);
+        }
+
+        let frame = Frame::from_normal(rec.normal);
+        let wo = frame.to_local(-vec3::unit_vector(r_in.direction));
+        if wo.z() <= 0.0 {
+            return false;
+        }
+
+        let mut choice = sampler.get_1d();
+        let lobes = self.lobes(wo.z());
+        // Rounding can leave the choice past the last probability
+        let picked = lobes
+            .iter()
+            .find(|(_, _, probability)| {
+                choice -= probability;
+                choice < 0.0 && *probability > 0.0
+            })
+            .or_else(|| {
+                lobes
+                    .iter()
+                    .rev()
+                    .find(|(_, _, probability)| *probability > 0.0)
+            });
+        let Some(&(lobe, share, probability)) = picked else {
+            return false;
+        };
+
+        let u = sampler.get_2d();
+        let sample = match lobe {
+            Lobe::Diffuse => self.sample_diffuse(wo, u),
+            Lobe::Specular => self.sample_specular(wo, u),
+            Lobe::Clearcoat => self.sample_clearcoat(wo, u),
+            Lobe::Transmission => {
+                if !glass.scatter(r_in, rec, path, attenuation, scattered, sampler) {
+                    return false;
+                }
+                // Light entering the object takes on the base colour
+                if scattered.direction.dot(&rec.normal) < 0.0 {
+                    *attenuation = *attenuation * self.base_colour;
+                }
+                *attenuation = *attenuation * (share / probability);
+                return true;
+            }
+        };
+        let Some((wi, weight)) = sample else {
+            return false;
+        };
+
+        *attenuation = weight * (share / probability);
+        *scattered = Ray::new_tm(rec.p, frame.to_world(wi), r_in.tm);
+        true
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Colour {
+        self.base_colour
+    }
+
+    fn medium(&self) -> Option<Medium> {
+        (self.transmission > 0.0)
+            .then_some(Medium::absorbing(self.refractive_index, Colour::default()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::common::INFINITY;
+    use crate::hittable::{Hittable, HittableList};
+    use crate::sphere::Sphere;
+    use crate::vec3::Point3;
+
+    // Returns the same value for every dimension so scattering decisions
+    // are deterministic
+    struct FixedSampler(f64);
+
+    impl Sampler for FixedSampler {
+        fn start_sample(&mut self, _x: usize, _y: usize, _index: u32) {}
+
+        fn get_1d(&mut self) -> f64 {
+            self.0
+        }
+
+        fn get_2d(&mut self) -> (f64, f64) {
+            (self.0, self.0)
+        }
+    }
+
+    #[test]
+    fn dispersive_glass_splits_wavelengths() {
+        let diamond = Dialectric::dispersive(Dispersion::DIAMOND, Colour::default());
+        let mut world = HittableList::new();
+        world.add(Box::new(Sphere::new(
+            Point3::new(0.0, 0.0, 0.0),
+            1.0,
+            Rc::new(diamond),
+        )));
+        let ray = Ray::new_tm(Point3::new(-5.0, 0.5, 0.0), Vec3::new(1.0, 0.0, 0.0), 0.0);
+
+        // Goes through the world so the wavelength has to survive the hit
+        let refract = |lambda: f64| {
+            let mut rec = HitRecord::new();
+            assert!(world.hit(&ray, 0.001, INFINITY, &mut rec));
+            let path = PathState {
+                exterior_ior: 1.0,
+                wavelength: Some(lambda),
+            };
+            let mut attenuation = Colour::default();
+            let mut scattered = Ray::default();
+            let material = rec.mat.clone().unwrap();
+            // A choice past the reflectance refracts
+            let mut sampler = FixedSampler(0.99);
+            assert!(material.scatter(
+                &ray,
+                &rec,
+                &path,
+                &mut attenuation,
+                &mut scattered,
+                &mut sampler
+            ));
+            (vec3::unit_vector(scattered.direction), rec.normal)
+        };
+
+        let (blue, normal) = refract(400.0);
+        let (red, _) = refract(700.0);
+        assert!(blue.dot(&normal) < 0.0 && red.dot(&normal) < 0.0);
+        // The higher index at 400nm bends blue closer to the normal
+        assert!(-blue.dot(&normal) > -red.dot(&normal) + 1e-3);
+    }
+
+    #[test]
+    fn conductor_film_uses_path_wavelength() {
+        let (eta, k) = Conductor::preset_ior("gold").unwrap();
+        let film = ThinFilm::new(300.0, 1.33);
+        let mut gold = Conductor::new(eta, k, 0.0);
+        gold.set_thin_film(Some(film));
+        let mut world = HittableList::new();
+        world.add(Box::new(Sphere::new(
+            Point3::new(0.0, 0.0, 0.0),
+            1.0,
+            Rc::new(gold),
+        )));
+        let ray = Ray::new_tm(Point3::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), 0.0);
+
+        let reflect = |path: PathState| {
+            let mut rec = HitRecord::new();
+            assert!(world.hit(&ray, 0.001, INFINITY, &mut rec));
+            let mut attenuation = Colour::default();
+            let mut scattered = Ray::default();
+            let mut sampler = FixedSampler(0.5);
+            let material = rec.mat.clone().unwrap();
+            assert!(material.scatter(
+                &ray,
+                &rec,
+                &path,
+                &mut attenuation,
+                &mut scattered,
+                &mut sampler
+            ));
+            attenuation
+        };
+        let spectral = |exterior_ior: f64, lambda: f64| {
+            reflect(PathState {
+                exterior_ior,
+                wavelength: Some(lambda),
+            })
+        };
+
+        for lambda in [450.0, 650.0] {
+            let base = (
+                spectrum::rgb_at_wavelength(eta, lambda),
+                spectrum::rgb_at_wavelength(k, lambda),
+            );
+            let r = film.reflectance(1.0, 1.0, base, lambda);
+            assert_eq!(spectral(1.0, lambda), Colour::new(r, r, r));
+        }
+        assert_ne!(spectral(1.0, 450.0), spectral(1.0, 650.0));
+        // Under water the film is matched more closely on its outer side
+        assert_ne!(spectral(1.0, 550.0), spectral(1.33, 550.0));
+        assert_ne!(reflect(PathState::new()), spectral(1.0, 550.0));
+    }
+
+    #[test]
+    fn anisotropy_follows_sphere_tangent() {
+        let (eta, k) = Conductor::preset_ior("aluminium").unwrap();
+        let brushed = Conductor::anisotropic(eta, k, 0.6, 0.0);
+        let mut world = HittableList::new();
+        world.add(Box::new(Sphere::new(
+            Point3::new(0.0, 0.0, 0.0),
+            1.0,
+            Rc::new(brushed),
+        )));
+
+        // Rough only along the longitude, so every sampled microfacet normal
+        // lies in the plane of the tangent and the normal wherever the
+        // sphere is hit
+        let mut rough = 0;
+        for (origin, u) in [
+            (Point3::new(-5.0, 0.5, 0.2), 0.3),
+            (Point3::new(0.4, -0.6, -5.0), 0.7),
+            (Point3::new(5.0, 0.3, -0.4), 0.5),
+            (Point3::new(0.2, 5.0, 0.5), 0.2),
+        ] {
+            // Aimed off centre so the view is not along the normal
+            let ray = Ray::new_tm(origin, Vec3::new(0.1, 0.2, 0.1) - origin, 0.0);
+            let mut rec = HitRecord::new();
+            assert!(world.hit(&ray, 0.001, INFINITY, &mut rec));
+            let mut attenuation = Colour::default();
+            let mut scattered = Ray::default();
+            let material = rec.mat.clone().unwrap();
+            if !material.scatter(
+                &ray,
+                &rec,
+                &PathState::new(),
+                &mut attenuation,
+                &mut scattered,
+                &mut FixedSampler(u),
+            ) {
+                continue;
+            }
+            let half = vec3::unit_vector(
+                vec3::unit_vector(scattered.direction) - vec3::unit_vector(ray.direction),
+            );
+            let bitangent = rec.normal.cross(&vec3::unit_vector(rec.tangent));
+            assert!(half.dot(&bitangent).abs() < 1e-9);
+            if half.dot(&rec.normal) < 0.999 {
+                rough += 1;
+            }
+        }
+        assert!(rough > 0);
+    }
+
+    // Hands out the coordinates of one point in turn, one per dimension
+    struct PointSampler {
+        point: Vec<f64>,
+        next: usize,
+    }
+
+    impl Sampler for PointSampler {
+        fn start_sample(&mut self, _x: usize, _y: usize, _index: u32) {}
+
+        fn get_1d(&mut self) -> f64 {
+            let u = self.point[self.next % self.point.len()];
+            self.next += 1;
+            u
+        }
+
+        fn get_2d(&mut self) -> (f64, f64) {
+            (self.get_1d(), self.get_1d())
+        }
+    }
+
+    // Fraction of light arriving from `wo` that the material reflects,
+    // integrated over a grid of `n` points per sample dimension
+    fn directional_albedo(material: &dyn Material, wo: Vec3, dimensions: u32, n: usize) -> Colour {
+        let points = n.pow(dimensions);
+        let mut total = Colour::default();
+        for index in 0..points {
+            let point = (0..dimensions)
+                .map(|d| ((index / n.pow(d)) % n) as f64 / n as f64 + 0.5 / n as f64)
+                .collect();
+            let mut sampler = PointSampler { point, next: 0 };
+            if let Some((_, weight)) = scatter_from(material, wo, &mut sampler) {
+                total += weight;
+            }
+        }
+        total / points as f64
+    }
+
+    // Hit at the origin of a surface facing +z, seen from `wo`
+    fn scatter_from(
+        material: &dyn Material,
+        wo: Vec3,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Vec3, Colour)> {
+        scatter_through(material, wo, true, sampler)
+    }
+
+    // As scatter_from, from inside the object unless `front_face`
+    fn scatter_through(
+        material: &dyn Material,
+        wo: Vec3,
+        front_face: bool,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Vec3, Colour)> {
+        let rec = HitRecord {
+            normal: Vec3::new(0.0, 0.0, 1.0),
+            front_face,
+            ..HitRecord::new()
+        };
+        let ray = Ray::new_tm(wo, -wo, 0.0);
+        let mut attenuation = Colour::default();
+        let mut scattered = Ray::default();
+        material
+            .scatter(
+                &ray,
+                &rec,
+                &PathState::new(),
+                &mut attenuation,
+                &mut scattered,
+                sampler,
+            )
+            .then(|| (vec3::unit_vector(scattered.direction), attenuation))
+    }
+
+    #[test]
+    fn metallic_principled_matches_conductor() {
+        let base = Colour::new(0.95, 0.64, 0.54);
+        for roughness in [0.0, 0.3, 0.7] {
+            let metal = Principled {
+                metallic: 1.0,
+                roughness,
+                ..Principled::new(base)
+            };
+            let conductor = Conductor::from_reflectance(base, roughness);
+            for cos_theta in [1.0_f64, 0.8, 0.5, 0.2] {
+                let wo = Vec3::new((1.0 - cos_theta * cos_theta).sqrt(), 0.0, cos_theta);
+                // Both are exact for a mirror seen head on, elsewhere
+                // Schlick's approximation drifts from exact Fresnel
+                let tolerance = if roughness == 0.0 && cos_theta == 1.0 {
+                    1e-9
+                } else {
+                    0.07
+                };
+                for u in [0.1, 0.4, 0.8] {
+                    let expected = scatter_from(&conductor, wo, &mut FixedSampler(u));
+                    let actual = scatter_from(&metal, wo, &mut FixedSampler(u));
+                    assert_eq!(expected.is_some(), actual.is_some());
+                    let (Some((expected_dir, expected)), Some((actual_dir, actual))) =
+                        (expected, actual)
+                    else {
+                        continue;
+                    };
+                    assert!((expected_dir - actual_dir).length() < 1e-12);
+                    let difference = expected - actual;
+                    for channel in [difference.x(), difference.y(), difference.z()] {
+                        assert!(channel.abs() < tolerance);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn coated_reflectance_approaches_fresnel_at_grazing_angles() {
+        let plastic = Coated::new(Colour::new(0.5, 0.5, 0.5), 1.5, 0.0);
+        let black = Coated::new(Colour::default(), 1.5, 0.0);
+        let mut last_gap = f64::INFINITY;
+        for cos_theta in [1.0_f64, 0.5, 0.2, 0.05, 0.01] {
+            let wo = Vec3::new((1.0 - cos_theta * cos_theta).sqrt(), 0.0, cos_theta);
+            let fresnel = fresnel_dielectric(cos_theta, 1.5);
+            // Without a base only the coat reflects
+            let coat = directional_albedo(&black, wo, 3, 16).x();
+            assert!((coat - fresnel).abs() < 1e-9);
+
+            // The base shows through less and less as the coat reflects more
+            let reflectance = directional_albedo(&plastic, wo, 3, 16).x();
+            let gap = reflectance - fresnel;
+            assert!(gap > 0.0 && reflectance <= 1.0);
+            assert!(gap < last_gap);
+            last_gap = gap;
+        }
+        assert!(last_gap < 0.03);
+    }
+
+    #[test]
+    fn oren_nayar_is_reciprocal_and_nearly_conserves_energy() {
+        let white = Colour::new(1.0, 1.0, 1.0);
+        let direction = |theta: f64, phi: f64| {
+            Vec3::new(
+                theta.sin() * phi.cos(),
+                theta.sin() * phi.sin(),
+                theta.cos(),
+            )
+        };
+        // Coefficients of the published model at sigma 0.2
+        let model = OrenNayar::new(white, 0.2);
+        assert!((model.a - 0.945_946).abs() < 1e-6 && (model.b - 0.138_462).abs() < 1e-6);
+
+        for sigma in [0.05, 0.2, 0.5, 1.0] {
+            let rough = OrenNayar::new(white, sigma);
+            for (theta_o, theta_i, phi) in [(0.3, 1.2, 0.4), (1.4, 0.1, 2.0), (0.9, 0.9, 0.0)] {
+                let (wo, wi) = (direction(theta_o, 0.0), direction(theta_i, phi));
+                assert!((rough.factor(wo, wi) - rough.factor(wi, wo)).abs() < 1e-12);
+            }
+            // The published model gains a little energy at grazing angles
+            for theta in [0.0, 0.6, 1.2, 1.5, 1.57] {
+                let albedo = directional_albedo(&rough, direction(theta, 0.0), 2, 64);
+                assert!(albedo.x() <= 1.015, "{} at sigma {}", albedo.x(), sigma);
+            }
+        }
+    }
+
+    #[test]
+    fn smooth_oren_nayar_is_lambertian() {
+        let albedo = Colour::new(0.8, 0.5, 0.2);
+        let smooth = OrenNayar::new(albedo, 0.0);
+        let lambertian = Lambertian::new(albedo);
+        for wo in [
+            Vec3::new(0.0, 0.0, 1.0),
+            vec3::unit_vector(Vec3::new(0.9, -0.3, 0.2)),
+        ] {
+            for u in [0.05, 0.3, 0.6, 0.95] {
+                let (direction, attenuation) =
+                    scatter_from(&smooth, wo, &mut FixedSampler(u)).unwrap();
+                let (expected_direction, expected) =
+                    scatter_from(&lambertian, wo, &mut FixedSampler(u)).unwrap();
+                assert_eq!(attenuation, expected);
+                assert!((direction - expected_direction).length() < 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn rough_dielectric_passes_white_furnace() {
+        let n = 24;
+        for roughness in [0.1, 0.5, 1.0] {
+            let glass = RoughDielectric::new(1.5, roughness);
+            for front_face in [true, false] {
+                for theta in [0.0_f64, 0.7, 1.3, 1.55] {
+                    let wo = Vec3::new(theta.sin(), 0.0, theta.cos());
+                    let mut total = 0.0;
+                    for index in 0..n * n * n {
+                        let point = [index % n, index / n % n, index / (n * n)]
+                            .map(|i| (i as f64 + 0.5) / n as f64)
+                            .to_vec();
+                        let mut sampler = PointSampler { point, next: 0 };
+                        if let Some((_, weight)) =
+                            scatter_through(&glass, wo, front_face, &mut sampler)
+                        {
+                            total += weight.x();
+                        }
+                    }
+                    // Without multiple scattering between microfacets rough
+                    // glass loses light, more so the rougher it is, but it
+                    // never gains any
+                    let albedo = total / (n * n * n) as f64;
+                    let least = if roughness < 0.2 { 0.9 } else { 0.0 };
+                    assert!(
+                        albedo <= 1.0 + 1e-9 && albedo > least,
+                        "{} at roughness {}, theta {}",
+                        albedo,
+                        roughness,
+                        theta
+                    );
+                }
+            }
+        }
+    }
+
+    // Compares a histogram of scattered directions, in bins of equal solid
+    // angle, with the density integrated over each bin
+    #[test]
+    fn rough_dielectric_pdf_matches_sampling() {
+        use crate::common::seed_random;
+        use crate::sampler::IndependentSampler;
+
+        const Z_BINS: usize = 24;
+        const PHI_BINS: usize = 16;
+        const SUBDIVISIONS: usize = 8;
+        let samples = 400_000;
+        let glass = RoughDielectric::new(1.5, 0.5);
+        let wo = vec3::unit_vector(Vec3::new(0.6, 0.2, 0.7));
+        let bin = |wi: Vec3| {
+            let z = ((wi.z() + 1.0) / 2.0 * Z_BINS as f64) as usize;
+            let phi = wi.y().atan2(wi.x()).rem_euclid(2.0 * PI);
+            let p = (phi / (2.0 * PI) * PHI_BINS as f64) as usize;
+            z.min(Z_BINS - 1) * PHI_BINS + p.min(PHI_BINS - 1)
+        };
+
+        for (eta, front_face) in [(1.5, true), (1.0 / 1.5, false)] {
+            seed_random(4);
+            let mut counts = [0; Z_BINS * PHI_BINS];
+            for _ in 0..samples {
+                if let Some((wi, _)) =
+                    scatter_through(&glass, wo, front_face, &mut IndependentSampler)
+                {
+                    counts[bin(wi)] += 1;
+                }
+            }
+
+            let mut expected = [0.0; Z_BINS * PHI_BINS];
+            let dz = 2.0 / (Z_BINS * SUBDIVISIONS) as f64;
+            let dphi = 2.0 * PI / (PHI_BINS * SUBDIVISIONS) as f64;
+            for i in 0..Z_BINS * SUBDIVISIONS {
+                for j in 0..PHI_BINS * SUBDIVISIONS {
+                    let z = -1.0 + (i as f64 + 0.5) * dz;
+                    let phi = (j as f64 + 0.5) * dphi;
+                    let r = (1.0 - z * z).sqrt();
+                    let wi = Vec3::new(r * phi.cos(), r * phi.sin(), z);
+                    expected[bin(wi)] += glass.pdf(wo, wi, eta) * dz * dphi;
+                }
+            }
+
+            // Paths the sampler rejects, below the surface on the wrong
+            // side, are missing from both
+            let accepted = counts.iter().sum::<usize>() as f64 / samples as f64;
+            assert!((expected.iter().sum::<f64>() - accepted).abs() < 0.002);
+            for (&count, &expected) in counts.iter().zip(&expected) {
+                let observed = count as f64 / samples as f64;
+                let sigma = (expected / samples as f64).sqrt();
+                assert!(
+                    (observed - expected).abs() < 5.0 * sigma + 0.01 * expected + 1e-5,
+                    "observed {}, expected {}",
+                    observed,
+                    expected
+                );
+            }
+        }
+    }
 }
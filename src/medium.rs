@@ -0,0 +1,198 @@
+use crate::colour::Colour;
+use crate::common::PI;
+use crate::microfacet::Frame;
+use crate::vec3::{self, Vec3};
+
+// Interior of a closed dielectric object
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Medium {
+    pub refractive_index: f64,
+    // Beer-Lambert coefficient per unit distance, zero for clear media
+    pub absorption: Colour,
+    // Scattering coefficient per unit distance, zero unless subsurface
+    pub scattering: Colour,
+    // Henyey-Greenstein g, positive scatters forwards
+    pub anisotropy: f64,
+}
+
+// Where a path travelling through a scattering medium next interacts
+pub enum Collision {
+    // Scattered inside the medium after `distance`
+    Scatter { distance: f64, weight: Colour },
+    // Reached the boundary without scattering
+    Surface { weight: Colour },
+}
+
+impl Medium {
+    pub fn absorbing(refractive_index: f64, absorption: Colour) -> Medium {
+        Medium {
+            refractive_index,
+            absorption,
+            scattering: Colour::default(),
+            anisotropy: 0.0,
+        }
+    }
+
+    pub fn is_scattering(&self) -> bool {
+        self.scattering != Colour::default()
+    }
+
+    // Light left after travelling `distance` through the medium
+    pub fn transmittance(&self, distance: f64) -> Colour {
+        let a = self.absorption;
+        Colour::new(
+            (-a.x() * distance).exp(),
+            (-a.y() * distance).exp(),
+            (-a.z() * distance).exp(),
+        )
+    }
+
+    // Samples a free flight distance from the extinction of one channel,
+    // weighted by the density averaged over all three so channels with very
+    // different mean free paths stay unbiased. Channels are picked in
+    // proportion to the weight the path carries in them so far, which keeps
+    // long walks through strongly coloured media from blowing up.
+    pub fn sample_collision(&self, max_distance: f64, path: Colour, u: (f64, f64)) -> Collision {
+        let extinction = self.absorption + self.scattering;
+        let channels = [extinction.x(), extinction.y(), extinction.z()];
+        let total = path.x() + path.y() + path.z();
+        let probabilities = if total > 0.0 {
+            [path.x() / total, path.y() / total, path.z() / total]
+        } else {
+            [1.0 / 3.0; 3]
+        };
+        let average = |c: Colour| {
+            probabilities[0] * c.x() + probabilities[1] * c.y() + probabilities[2] * c.z()
+        };
+        let channel = if u.0 < probabilities[0] {
+            0
+        } else if u.0 < probabilities[0] + probabilities[1] {
+            1
+        } else {
+            2
+        };
+        let distance = -(1.0 - u.1).ln() / channels[channel];
+        let transmittance = |d: f64| {
+            Colour::new(
+                (-extinction.x() * d).exp(),
+                (-extinction.y() * d).exp(),
+                (-extinction.z() * d).exp(),
+            )
+        };
+
+        if distance < max_distance {
+            let t = transmittance(distance);
+            let pdf = average(extinction * t);
+            Collision::Scatter {
+                distance,
+                weight: self.scattering * t / pdf,
+            }
+        } else {
+            let t = transmittance(max_distance);
+            let probability = average(t);
+            Collision::Surface {
+                weight: t / probability,
+            }
+        }
+    }
+
+    // New direction after scattering off a particle, sampled exactly from
+    // the Henyey-Greenstein phase function so it carries no weight
+    pub fn sample_phase(&self, direction: Vec3, u: (f64, f64)) -> Vec3 {
+        let g = self.anisotropy;
+        let cos_theta = if g.abs() < 1e-3 {
+            1.0 - 2.0 * u.0
+        } else {
+            let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * u.0);
+            (1.0 + g * g - s * s) / (2.0 * g)
+        }
+        .clamp(-1.0, 1.0);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * u.1;
+        Frame::from_normal(vec3::unit_vector(direction)).to_world(Vec3::new(
+            sin_theta * phi.cos(),
+            sin_theta * phi.sin(),
+            cos_theta,
+        ))
+    }
+}
+
+// Media a path is currently inside, innermost last, keyed by the object id of
+// their boundary. Properly nested objects such as glass in water then see the
+// right index on both sides of every surface.
+#[derive(Clone, Default, Debug)]
+pub struct MediumStack {
+    entries: Vec<(usize, Medium)>,
+}
+
+impl MediumStack {
+    pub fn new() -> MediumStack {
+        Default::default()
+    }
+
+    pub fn current(&self) -> Option<Medium> {
+        self.entries.last().map(|(_, medium)| *medium)
+    }
+
+    // Index of the medium on the outside of `object_id`, the one a path
+    // entering it comes from or a path leaving it goes into
+    pub fn exterior_ior(&self, object_id: usize, front_face: bool) -> f64 {
+        let enclosing = if front_face {
+            self.entries.last()
+        } else {
+            self.entries
+                .iter()
+                .rev()
+                .skip_while(|(id, _)| *id != object_id)
+                .nth(1)
+        };
+        enclosing.map_or(1.0, |(_, medium)| medium.refractive_index)
+    }
+
+    pub fn enter(&mut self, object_id: usize, medium: Medium) {
+        self.entries.push((object_id, medium));
+    }
+
+    pub fn exit(&mut self, object_id: usize) {
+        if let Some(index) = self.entries.iter().rposition(|(id, _)| *id == object_id) {
+            self.entries.remove(index);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn medium(refractive_index: f64) -> Medium {
+        Medium::absorbing(refractive_index, Colour::default())
+    }
+
+    #[test]
+    fn glass_inside_water() {
+        let (water, glass) = (1, 2);
+        let mut media = MediumStack::new();
+        assert_eq!(media.exterior_ior(water, true), 1.0);
+
+        media.enter(water, medium(1.33));
+        assert_eq!(media.exterior_ior(glass, true), 1.33);
+        media.enter(glass, medium(1.5));
+        // Leaving the glass goes back into the water, not air
+        assert_eq!(media.exterior_ior(glass, false), 1.33);
+        media.exit(glass);
+
+        assert_eq!(media.current(), Some(medium(1.33)));
+        assert_eq!(media.exterior_ior(water, false), 1.0);
+        media.exit(water);
+        assert_eq!(media.current(), None);
+    }
+
+    #[test]
+    fn beer_lambert_transmittance() {
+        let absorbing = Medium::absorbing(1.5, Colour::new(0.0, 1.0, 2.0));
+        let t = absorbing.transmittance(0.5);
+        assert_eq!(t.x(), 1.0);
+        assert!((t.y() - (-0.5_f64).exp()).abs() < 1.0e-12);
+        assert!((t.z() - (-1.0_f64).exp()).abs() < 1.0e-12);
+    }
+}
@@ -0,0 +1,349 @@
+use crate::colour::Colour;
+use crate::common::PI;
+use crate::vec3::{unit_vector, Vec3};
+
+// Orthonormal shading frame around a normal, local coordinates have the
+// normal along +z
+#[derive(Clone, Copy)]
+pub struct Frame {
+    s: Vec3,
+    t: Vec3,
+    n: Vec3,
+}
+
+impl Frame {
+    // Branchless basis of Duff et al. 2017, continuous except at n.z = 0
+    pub fn from_normal(n: Vec3) -> Frame {
+        let sign = 1.0_f64.copysign(n.z());
+        let a = -1.0 / (sign + n.z());
+        let b = n.x() * n.y() * a;
+        Frame {
+            s: Vec3::new(1.0 + sign * n.x() * n.x() * a, sign * b, -sign * n.x()),
+            t: Vec3::new(b, sign + n.y() * n.y() * a, -n.y()),
+            n,
+        }
+    }
+
+    // Frame with its s axis along the part of `tangent` perpendicular to the
+    // normal, so anisotropic roughness follows the surface. Falls back to an
+    // arbitrary basis where the tangent is missing or parallel to the normal.
+    pub fn from_tangent(n: Vec3, tangent: Vec3) -> Frame {
+        let s = tangent - tangent.dot(&n) * n;
+        if s.length_squared() < 1.0e-12 {
+            return Frame::from_normal(n);
+        }
+        let s = unit_vector(s);
+        Frame {
+            s,
+            t: n.cross(&s),
+            n,
+        }
+    }
+
+    pub fn to_local(&self, v: Vec3) -> Vec3 {
+        Vec3::new(v.dot(&self.s), v.dot(&self.t), v.dot(&self.n))
+    }
+
+    pub fn to_world(&self, v: Vec3) -> Vec3 {
+        v.x() * self.s + v.y() * self.t + v.z() * self.n
+    }
+}
+
+// Below this alpha the surface is treated as a perfect mirror
+const SMOOTH_ALPHA: f64 = 1.0e-3;
+
+// Trowbridge-Reitz (GGX) distribution of microfacet normals with Smith
+// shadowing-masking, in the local frame of a `Frame`. `alpha_x` is the
+// roughness along the frame's s axis and `alpha_y` along its t axis.
+#[derive(Clone, Copy, Debug)]
+pub struct Ggx {
+    alpha_x: f64,
+    alpha_y: f64,
+}
+
+impl Ggx {
+    // Roughness is squared into alpha so it reads roughly linear
+    pub fn from_roughness(roughness_x: f64, roughness_y: f64) -> Ggx {
+        Ggx {
+            alpha_x: roughness_x * roughness_x,
+            alpha_y: roughness_y * roughness_y,
+        }
+    }
+
+    pub fn is_smooth(&self) -> bool {
+        self.alpha_x.max(self.alpha_y) < SMOOTH_ALPHA
+    }
+
+    fn lambda(&self, w: Vec3) -> f64 {
+        if w.z() == 0.0 {
+            return f64::INFINITY;
+        }
+        let tan2 =
+            ((self.alpha_x * w.x()).powi(2) + (self.alpha_y * w.y()).powi(2)) / (w.z() * w.z());
+        0.5 * (-1.0 + (1.0 + tan2).sqrt())
+    }
+
+    pub fn g1(&self, w: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    // Height correlated masking and shadowing
+    pub fn g2(&self, wo: Vec3, wi: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    // Density of microfacet normals, D(m)
+    pub fn d(&self, m: Vec3) -> f64 {
+        if m.z() <= 0.0 {
+            return 0.0;
+        }
+        let e = (m.x() / self.alpha_x).powi(2) + (m.y() / self.alpha_y).powi(2) + m.z() * m.z();
+        1.0 / (PI * self.alpha_x * self.alpha_y * e * e)
+    }
+
+    // Density over solid angle of the normals `sample_visible_normal` picks
+    pub fn visible_normal_pdf(&self, wo: Vec3, m: Vec3) -> f64 {
+        self.g1(wo) * wo.dot(&m).max(0.0) * self.d(m) / wo.z()
+    }
+
+    // Samples a microfacet normal visible from `wo` (Heitz 2018), `wo` must be
+    // in the upper hemisphere
+    pub fn sample_visible_normal(&self, wo: Vec3, u: (f64, f64)) -> Vec3 {
+        // Stretch the view direction to the configuration of a hemisphere
+        let vh = unit_vector(Vec3::new(
+            self.alpha_x * wo.x(),
+            self.alpha_y * wo.y(),
+            wo.z(),
+        ));
+
+        let length_squared = vh.x() * vh.x() + vh.y() * vh.y();
+        let t1 = if length_squared > 0.0 {
+            Vec3::new(-vh.y(), vh.x(), 0.0) / length_squared.sqrt()
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = vh.cross(&t1);
+
+        // Uniform disk sample warped towards the visible half
+        let r = u.0.sqrt();
+        let phi = 2.0 * PI * u.1;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.z());
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+        let nh = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * vh;
+
+        // Unstretch back to the ellipsoid
+        unit_vector(Vec3::new(
+            self.alpha_x * nh.x(),
+            self.alpha_y * nh.y(),
+            nh.z().max(0.0),
+        ))
+    }
+}
+
+// Unpolarised Fresnel reflectance of a conductor with complex index of
+// refraction eta + ik, per colour channel
+pub fn fresnel_conductor(cos_theta: f64, eta: Colour, k: Colour) -> Colour {
+    let channel = |eta: f64, k: f64| {
+        let cos2 = cos_theta.clamp(0.0, 1.0).powi(2);
+        let sin2 = 1.0 - cos2;
+        let t0 = eta * eta - k * k - sin2;
+        let a2b2 = (t0 * t0 + 4.0 * eta * eta * k * k).sqrt();
+        let a = (0.5 * (a2b2 + t0)).max(0.0).sqrt();
+
+        let t1 = a2b2 + cos2;
+        let t2 = 2.0 * cos_theta * a;
+        let rs = (t1 - t2) / (t1 + t2);
+
+        let t3 = cos2 * a2b2 + sin2 * sin2;
+        let t4 = t2 * sin2;
+        let rp = rs * (t3 - t4) / (t3 + t4);
+        0.5 * (rs + rp)
+    };
+    Colour::new(
+        channel(eta.x(), k.x()),
+        channel(eta.y(), k.y()),
+        channel(eta.z(), k.z()),
+    )
+}
+
+// Fresnel reflectance of a dielectric interface, `eta` is the index on the
+// far side over the index on the side of the incoming direction
+pub fn fresnel_dielectric(cos_theta_i: f64, eta: f64) -> f64 {
+    let cos_i = cos_theta_i.clamp(0.0, 1.0);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let rs = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    let rp = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    0.5 * (rs * rs + rp * rp)
+}
+
+// Fresnel reflectance averaged over the cosine weighted hemisphere, the
+// share of diffuse light an interface reflects back
+pub fn fresnel_diffuse_reflectance(eta: f64) -> f64 {
+    // Midpoint rule in cos^2, which is uniform for the cosine weighting
+    let steps = 1024;
+    (0..steps)
+        .map(|i| fresnel_dielectric(((i as f64 + 0.5) / steps as f64).sqrt(), eta))
+        .sum::<f64>()
+        / steps as f64
+}
+
+// Schlick's approximation, for materials given by their reflectance at
+// normal incidence rather than an index
+pub fn fresnel_schlick(cos_theta: f64, f0: Colour) -> Colour {
+    let weight = (1.0 - cos_theta.clamp(0.0, 1.0)).powi(5);
+    f0 + weight * (Colour::new(1.0, 1.0, 1.0) - f0)
+}
+
+// Samples a normal from the GTR1 (Berry) distribution used for the Disney
+// clearcoat, proportional to D(m) cos(theta_m)
+pub fn sample_gtr1(alpha: f64, u: (f64, f64)) -> Vec3 {
+    let a2 = alpha * alpha;
+    let cos2_theta = (1.0 - a2.powf(1.0 - u.0)) / (1.0 - a2);
+    let sin_theta = (1.0 - cos2_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * u.1;
+    Vec3::new(
+        sin_theta * phi.cos(),
+        sin_theta * phi.sin(),
+        cos2_theta.sqrt(),
+    )
+}
+
+// Refracts `wo` through a surface with normal `m` on its side, None on total
+// internal reflection. `eta` is as for `fresnel_dielectric`.
+pub fn refract(wo: Vec3, m: Vec3, eta: f64) -> Option<Vec3> {
+    let cos_i = wo.dot(&m);
+    let sin2_t = (1.0 - cos_i * cos_i).max(0.0) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return None;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some(-wo / eta + (cos_i / eta - cos_t) * m)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec3;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1.0e-9,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn fresnel_dielectric_matches_reference_values() {
+        // Air to glass at normal incidence, 45 degrees and Brewster's angle
+        assert_close(fresnel_dielectric(1.0, 1.5), 0.04);
+        assert_close(
+            fresnel_dielectric(45.0_f64.to_radians().cos(), 1.5),
+            0.05023991101223595,
+        );
+        assert_close(
+            fresnel_dielectric(1.5_f64.atan().cos(), 1.5),
+            0.07396449704142012,
+        );
+        // Glass to air below the critical angle and air to water at grazing angles
+        assert_close(
+            fresnel_dielectric(30.0_f64.to_radians().cos(), 1.0 / 1.5),
+            0.055190167295375916,
+        );
+        assert_close(
+            fresnel_dielectric(80.0_f64.to_radians().cos(), 1.33),
+            0.3469160962622535,
+        );
+    }
+
+    #[test]
+    fn fresnel_dielectric_total_internal_reflection() {
+        // The critical angle from glass to air is about 41.8 degrees
+        assert_eq!(
+            fresnel_dielectric(60.0_f64.to_radians().cos(), 1.0 / 1.5),
+            1.0
+        );
+        assert!(fresnel_dielectric(40.0_f64.to_radians().cos(), 1.0 / 1.5) < 1.0);
+    }
+
+    #[test]
+    fn fresnel_conductor_at_normal_incidence() {
+        let (eta, k): (f64, f64) = (0.2, 3.9);
+        let expected = ((eta - 1.0).powi(2) + k * k) / ((eta + 1.0).powi(2) + k * k);
+        let f = fresnel_conductor(1.0, Colour::new(eta, eta, eta), Colour::new(k, k, k));
+        assert_close(f.x(), expected);
+    }
+
+    #[test]
+    fn refraction_follows_snells_law() {
+        let n = Vec3::new(0.0, 0.0, 1.0);
+        let theta = 30.0_f64.to_radians();
+        let wo = Vec3::new(theta.sin(), 0.0, theta.cos());
+        let wi = refract(wo, n, 1.5).unwrap();
+        assert_close(wi.length(), 1.0);
+        assert_close((-wi.x()).atan2(-wi.z()).sin() * 1.5, theta.sin());
+
+        // The same ray through vec3::refract, which takes the incoming direction
+        let wi2 = vec3::refract(-wo, n, 1.0 / 1.5).unwrap();
+        assert_close(wi2.x(), wi.x());
+        assert_close(wi2.z(), wi.z());
+    }
+
+    #[test]
+    fn refraction_reports_total_internal_reflection() {
+        let n = Vec3::new(0.0, 0.0, 1.0);
+        let theta = 60.0_f64.to_radians();
+        let wo = Vec3::new(theta.sin(), 0.0, theta.cos());
+        assert!(refract(wo, n, 1.0 / 1.5).is_none());
+        assert!(vec3::refract(-wo, n, 1.5).is_none());
+    }
+
+    #[test]
+    fn tangent_frame_follows_tangent() {
+        let n = vec3::unit_vector(Vec3::new(0.3, 0.8, -0.5));
+        let tangent = Vec3::new(-0.5, 0.0, -0.3);
+        let frame = Frame::from_tangent(n, tangent);
+        let s = frame.to_world(Vec3::new(1.0, 0.0, 0.0));
+        let t = frame.to_world(Vec3::new(0.0, 1.0, 0.0));
+        assert_close(s.dot(&vec3::unit_vector(tangent)), 1.0);
+        assert_close(s.dot(&n), 0.0);
+        assert_close(t.dot(&n), 0.0);
+        assert_close(t.length(), 1.0);
+        assert_close(frame.to_world(Vec3::new(0.0, 0.0, 1.0)).dot(&n), 1.0);
+
+        // Without a usable tangent any orthonormal frame will do
+        let frame = Frame::from_tangent(n, Vec3::default());
+        assert_close(frame.to_world(Vec3::new(1.0, 0.0, 0.0)).dot(&n), 0.0);
+    }
+
+    // Projected microfacet area adds up to the macro surface, for any view
+    // the visible normals make up a whole distribution
+    #[test]
+    fn distribution_is_normalised() {
+        let n = 400;
+        for ggx in [Ggx::from_roughness(0.5, 0.5), Ggx::from_roughness(0.7, 0.3)] {
+            let wo = unit_vector(Vec3::new(0.5, -0.2, 0.8));
+            let (mut projected, mut visible) = (0.0, 0.0);
+            for i in 0..n {
+                for j in 0..n {
+                    // Midpoint rule over the hemisphere in cos(theta) and phi
+                    let z = (i as f64 + 0.5) / n as f64;
+                    let phi = 2.0 * PI * (j as f64 + 0.5) / n as f64;
+                    let r = (1.0 - z * z).sqrt();
+                    let m = Vec3::new(r * phi.cos(), r * phi.sin(), z);
+                    let solid_angle = 2.0 * PI / (n * n) as f64;
+                    projected += ggx.d(m) * z * solid_angle;
+                    visible += ggx.visible_normal_pdf(wo, m) * solid_angle;
+                }
+            }
+            assert!((projected - 1.0).abs() < 5e-3, "{}", projected);
+            assert!((visible - 1.0).abs() < 5e-3, "{}", visible);
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use std::fs::File;
+use std::io::BufWriter;
+
+use minifb::{Key, KeyRepeat, MouseButton, MouseMode, Window, WindowOptions};
+
+use crate::camera::Camera;
+use crate::colour;
+use crate::common::degrees_to_radians;
+use crate::film::Film;
+use crate::hittable::Hittable;
+use crate::progress::Silent;
+use crate::vec3::Vec3;
+
+const ORBIT_KEY_STEP: f64 = 5.0;
+const ORBIT_MOUSE_SCALE: f64 = 0.5;
+
+// Interactive window showing the progressive accumulation. Dragging with the
+// left mouse button or the arrow keys orbit `look_from` around `look_at` and
+// restart accumulation, S saves the current frame to `save_path`, Escape quits.
+pub fn run(camera: &mut Camera, world: &dyn Hittable, save_path: &str) {
+    let mut state = camera.new_render_state();
+    let (width, height) = (state.film().width(), state.film().height());
+    let mut window = Window::new(
+        "ray-tracing preview",
+        width,
+        height,
+        WindowOptions {
+            scale: minifb::Scale::X2,
+            ..WindowOptions::default()
+        },
+    )
+    .expect("Opening preview window");
+
+    let mut buffer = vec![0u32; width * height];
+    let mut last_mouse: Option<(f32, f32)> = None;
+
+    while window.is_open() && !window.is_key_down(Key::Escape) {
+        let mut yaw = 0.0;
+        let mut pitch = 0.0;
+        if window.is_key_down(Key::Left) {
+            yaw -= ORBIT_KEY_STEP;
+        }
+        if window.is_key_down(Key::Right) {
+            yaw += ORBIT_KEY_STEP;
+        }
+        if window.is_key_down(Key::Up) {
+            pitch += ORBIT_KEY_STEP;
+        }
+        if window.is_key_down(Key::Down) {
+            pitch -= ORBIT_KEY_STEP;
+        }
+
+        let mouse = window.get_mouse_pos(MouseMode::Discard);
+        if window.get_mouse_down(MouseButton::Left) {
+            if let (Some((x, y)), Some((last_x, last_y))) = (mouse, last_mouse) {
+                yaw -= (x - last_x) as f64 * ORBIT_MOUSE_SCALE;
+                pitch += (y - last_y) as f64 * ORBIT_MOUSE_SCALE;
+            }
+            last_mouse = mouse;
+        } else {
+            last_mouse = None;
+        }
+
+        if yaw != 0.0 || pitch != 0.0 {
+            orbit(camera, yaw, pitch);
+            state = camera.new_render_state();
+        }
+
+        if window.is_key_pressed(Key::S, KeyRepeat::No) {
+            let file = File::create(save_path).expect("Creating preview frame");
+            state
+                .film()
+                .to_framebuffer()
+                .write_ppm(&mut BufWriter::new(file));
+            eprintln!("\nSaved {}", save_path);
+        }
+
+        if camera.is_finished(&state) {
+            window.update();
+        } else {
+            camera.render_pass(world, &[], &mut state, &mut Silent);
+            fill_buffer(&mut buffer, state.film());
+            window
+                .update_with_buffer(&buffer, width, height)
+                .expect("Updating preview window");
+        }
+    }
+}
+
+// Rotates the view direction about the world up axis and tilts it, keeping
+// the distance to `look_at`
+fn orbit(camera: &mut Camera, yaw_degrees: f64, pitch_degrees: f64) {
+    let look_at = camera.look_at();
+    let offset = camera.look_from() - look_at;
+    let radius = offset.length();
+
+    let yaw = f64::atan2(offset.x(), offset.z()) + degrees_to_radians(yaw_degrees);
+    let limit = degrees_to_radians(89.0);
+    let pitch =
+        (f64::asin(offset.y() / radius) + degrees_to_radians(pitch_degrees)).clamp(-limit, limit);
+
+    let direction = Vec3::new(
+        pitch.cos() * yaw.sin(),
+        pitch.sin(),
+        pitch.cos() * yaw.cos(),
+    );
+    camera.set_view(look_at + radius * direction, look_at);
+}
+
+fn fill_buffer(buffer: &mut [u32], film: &Film) {
+    for y in 0..film.height() {
+        for x in 0..film.width() {
+            let [r, g, b] = colour::to_rgb8(film.pixel(x, y), 1);
+            buffer[y * film.width() + x] = (r as u32) << 16 | (g as u32) << 8 | b as u32;
+        }
+    }
+}
@@ -0,0 +1,269 @@
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+use crate::checkpoint::RenderState;
+use crate::film::Film;
+use crate::stats::RenderStats;
+
+// Snapshot of how far a render has got. Sample and ray counts cover this run
+// only, the fraction also includes work restored from a checkpoint.
+#[derive(Clone, Copy, Debug)]
+pub struct Progress {
+    pub pixels_done: usize,
+    pub pixels: usize,
+    pub samples: u64,
+    pub rays: u64,
+    pub fraction: f64,
+    pub elapsed: Duration,
+    pub eta: Option<Duration>,
+}
+
+impl Progress {
+    pub fn rays_per_second(&self) -> f64 {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds > 0.0 {
+            self.rays as f64 / seconds
+        } else {
+            0.0
+        }
+    }
+}
+
+// Tracks the current run of a render, it is not part of a checkpoint
+pub(crate) struct ProgressTracker {
+    started: Instant,
+    start_fraction: Option<f64>,
+    pub samples: u64,
+    pub stats: RenderStats,
+}
+
+impl ProgressTracker {
+    pub fn new() -> ProgressTracker {
+        ProgressTracker {
+            started: Instant::now(),
+            start_fraction: None,
+            samples: 0,
+            stats: RenderStats::default(),
+        }
+    }
+
+    // Remembers where this run started so the ETA ignores resumed work
+    pub fn start(&mut self, fraction: f64) {
+        self.start_fraction.get_or_insert(fraction);
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    pub fn stats(&self) -> RenderStats {
+        RenderStats {
+            elapsed: self.elapsed(),
+            ..self.stats.clone()
+        }
+    }
+
+    pub fn progress(&self, pixels_done: usize, pixels: usize, fraction: f64) -> Progress {
+        let start_fraction = self.start_fraction.unwrap_or(0.0);
+        let elapsed = self.started.elapsed();
+        let done = fraction - start_fraction;
+        let eta = (done > 0.0)
+            .then(|| Duration::from_secs_f64(elapsed.as_secs_f64() * (1.0 - fraction) / done));
+
+        Progress {
+            pixels_done,
+            pixels,
+            samples: self.samples,
+            rays: self.stats.rays(),
+            fraction,
+            elapsed,
+            eta,
+        }
+    }
+}
+
+pub trait RenderObserver {
+    // Called regularly while a pass is rendering
+    fn on_progress(&mut self, _progress: &Progress) {}
+
+    // Called with the film after every row of a pass, for work that should
+    // not wait for the whole pass
+    fn on_row(&mut self, _film: &Film) {}
+
+    // Called with the accumulated state after every pass
+    fn on_pass(&mut self, _state: &RenderState) {}
+
+    fn on_finish(&mut self, _progress: &Progress) {}
+}
+
+// Plain closures observe finished passes
+impl<F: FnMut(&RenderState)> RenderObserver for F {
+    fn on_pass(&mut self, state: &RenderState) {
+        self(state)
+    }
+}
+
+pub struct Silent;
+
+impl RenderObserver for Silent {}
+
+const BAR_WIDTH: usize = 30;
+const BAR_REDRAW: Duration = Duration::from_millis(100);
+
+// Single line progress bar on stderr
+#[derive(Default)]
+pub struct ProgressBar {
+    last_draw: Option<Instant>,
+}
+
+impl ProgressBar {
+    pub fn new() -> ProgressBar {
+        Default::default()
+    }
+
+    fn draw(&self, progress: &Progress) {
+        let filled = ((progress.fraction * BAR_WIDTH as f64) as usize).min(BAR_WIDTH);
+        let eta = progress.eta.map_or(String::from("--"), |eta| {
+            format!("{:.1}s", eta.as_secs_f64())
+        });
+        eprint!(
+            "\r[{}{}] {:5.1}% | {:.1}s elapsed | ETA {} | {:.2} Mrays/s   ",
+            "#".repeat(filled),
+            "-".repeat(BAR_WIDTH - filled),
+            100.0 * progress.fraction,
+            progress.elapsed.as_secs_f64(),
+            eta,
+            progress.rays_per_second() / 1.0e6,
+        );
+    }
+}
+
+impl RenderObserver for ProgressBar {
+    fn on_progress(&mut self, progress: &Progress) {
+        if self
+            .last_draw
+            .is_some_and(|last| last.elapsed() < BAR_REDRAW)
+        {
+            return;
+        }
+        self.last_draw = Some(Instant::now());
+        self.draw(progress);
+    }
+
+    fn on_finish(&mut self, progress: &Progress) {
+        self.draw(progress);
+        if progress.fraction < 1.0 {
+            eprintln!("\nStopped");
+        } else {
+            eprintln!("\nDone!");
+        }
+    }
+}
+
+// One JSON object per line, at most once per interval, for batch logs
+pub struct JsonReporter<W: Write> {
+    out: W,
+    interval: Duration,
+    last_report: Option<Instant>,
+}
+
+impl<W: Write> JsonReporter<W> {
+    pub fn new(out: W, interval: Duration) -> JsonReporter<W> {
+        JsonReporter {
+            out,
+            interval,
+            last_report: None,
+        }
+    }
+
+    fn report(&mut self, progress: &Progress, finished: bool) {
+        let eta = progress
+            .eta
+            .map_or(String::from("null"), |eta| eta.as_secs_f64().to_string());
+        writeln!(
+            self.out,
+            "{{\"pixels_done\":{},\"pixels\":{},\"samples\":{},\"rays\":{},\"progress\":{},\"elapsed\":{},\"eta\":{},\"rays_per_second\":{},\"finished\":{}}}",
+            progress.pixels_done,
+            progress.pixels,
+            progress.samples,
+            progress.rays,
+            progress.fraction,
+            progress.elapsed.as_secs_f64(),
+            eta,
+            progress.rays_per_second(),
+            finished,
+        )
+        .expect("Writing progress");
+    }
+}
+
+impl<W: Write> RenderObserver for JsonReporter<W> {
+    fn on_progress(&mut self, progress: &Progress) {
+        if self
+            .last_report
+            .is_some_and(|last| last.elapsed() < self.interval)
+        {
+            return;
+        }
+        self.last_report = Some(Instant::now());
+        self.report(progress, false);
+    }
+
+    fn on_finish(&mut self, progress: &Progress) {
+        self.report(progress, true);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn eta_only_counts_this_run() {
+        let mut tracker = ProgressTracker::new();
+        tracker.start(0.4);
+        tracker.start(0.6);
+        assert!(tracker.progress(0, 10, 0.4).eta.is_none());
+
+        std::thread::sleep(Duration::from_millis(20));
+        let progress = tracker.progress(7, 10, 0.7);
+        let seconds = progress.elapsed.as_secs_f64();
+        assert!(seconds >= 0.02);
+        // 0.3 done in this run leaves 0.3 to go, so as long again
+        let eta = progress.eta.unwrap().as_secs_f64();
+        assert!((eta - seconds).abs() < 1e-6);
+        assert_eq!(progress.pixels_done, 7);
+        assert_eq!(progress.pixels, 10);
+    }
+
+    fn progress(eta: Option<Duration>) -> Progress {
+        Progress {
+            pixels_done: 3,
+            pixels: 12,
+            samples: 40,
+            rays: 100,
+            fraction: 0.25,
+            elapsed: Duration::from_secs(2),
+            eta,
+        }
+    }
+
+    #[test]
+    fn json_reporter_writes_one_line_per_report() {
+        let mut reporter = JsonReporter::new(Vec::new(), Duration::from_secs(1000));
+        reporter.on_progress(&progress(Some(Duration::from_secs(6))));
+        // Within the interval
+        reporter.on_progress(&progress(Some(Duration::from_secs(5))));
+        reporter.on_finish(&progress(None));
+
+        let output = String::from_utf8(reporter.out).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "{\"pixels_done\":3,\"pixels\":12,\"samples\":40,\"rays\":100,\"progress\":0.25,\"elapsed\":2,\"eta\":6,\"rays_per_second\":50,\"finished\":false}",
+                "{\"pixels_done\":3,\"pixels\":12,\"samples\":40,\"rays\":100,\"progress\":0.25,\"elapsed\":2,\"eta\":null,\"rays_per_second\":50,\"finished\":true}",
+            ]
+        );
+    }
+}
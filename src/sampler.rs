@@ -0,0 +1,529 @@
+use std::sync::OnceLock;
+
+use crate::common::random_double;
+
+// Source of sample values for every random decision along a camera path.
+// Dimensions are consumed in order: pixel jitter, lens, time, then the BSDF
+// samples of each bounce.
+pub trait Sampler {
+    fn start_sample(&mut self, x: usize, y: usize, index: u32);
+    fn get_1d(&mut self) -> f64;
+    fn get_2d(&mut self) -> (f64, f64);
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SamplerKind {
+    Independent,
+    Stratified,
+    Halton,
+    Sobol,
+    BlueNoise,
+}
+
+impl SamplerKind {
+    pub const ALL: [SamplerKind; 5] = [
+        SamplerKind::Independent,
+        SamplerKind::Stratified,
+        SamplerKind::Halton,
+        SamplerKind::Sobol,
+        SamplerKind::BlueNoise,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SamplerKind::Independent => "independent",
+            SamplerKind::Stratified => "stratified",
+            SamplerKind::Halton => "halton",
+            SamplerKind::Sobol => "sobol",
+            SamplerKind::BlueNoise => "bluenoise",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<SamplerKind> {
+        SamplerKind::ALL
+            .into_iter()
+            .find(|kind| kind.name() == name)
+    }
+
+    pub fn create(self, samples_per_pixel: u32) -> Box<dyn Sampler> {
+        match self {
+            SamplerKind::Independent => Box::new(IndependentSampler),
+            SamplerKind::Stratified => Box::new(StratifiedSampler::new(samples_per_pixel)),
+            SamplerKind::Halton => Box::new(HaltonSampler::default()),
+            SamplerKind::Sobol => Box::new(SobolSampler::default()),
+            SamplerKind::BlueNoise => Box::new(BlueNoiseSampler::default()),
+        }
+    }
+}
+
+pub struct IndependentSampler;
+
+impl Sampler for IndependentSampler {
+    fn start_sample(&mut self, _x: usize, _y: usize, _index: u32) {}
+
+    fn get_1d(&mut self) -> f64 {
+        random_double()
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (random_double(), random_double())
+    }
+}
+
+// Jittered grid, the strata of each dimension are visited in a
+// per-pixel random order so dimensions stay decorrelated
+pub struct StratifiedSampler {
+    samples_per_pixel: u32,
+    grid: u32,
+    pixel: u32,
+    index: u32,
+    dimension: u32,
+}
+
+impl StratifiedSampler {
+    pub fn new(samples_per_pixel: u32) -> StratifiedSampler {
+        StratifiedSampler {
+            samples_per_pixel: samples_per_pixel.max(1),
+            grid: (samples_per_pixel.max(1) as f64).sqrt().floor() as u32,
+            pixel: 0,
+            index: 0,
+            dimension: 0,
+        }
+    }
+
+    fn next_seed(&mut self) -> u32 {
+        self.dimension += 1;
+        hash_combine(self.pixel, self.dimension)
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_sample(&mut self, x: usize, y: usize, index: u32) {
+        self.pixel = pixel_hash(x, y);
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let n = self.samples_per_pixel;
+        let stratum = permute(self.index % n, n, self.next_seed());
+        (stratum as f64 + random_double()) / n as f64
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let n = self.grid * self.grid;
+        let stratum = permute(self.index % n, n, self.next_seed());
+        let (sx, sy) = (stratum % self.grid, stratum / self.grid);
+        (
+            (sx as f64 + random_double()) / self.grid as f64,
+            (sy as f64 + random_double()) / self.grid as f64,
+        )
+    }
+}
+
+const PRIMES: [u32; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131,
+];
+
+// Halton sequence with a per-pixel Cranley-Patterson rotation.
+// Dimensions past the prime table fall back to independent samples.
+#[derive(Default)]
+pub struct HaltonSampler {
+    pixel: u32,
+    index: u32,
+    dimension: usize,
+}
+
+impl Sampler for HaltonSampler {
+    fn start_sample(&mut self, x: usize, y: usize, index: u32) {
+        self.pixel = pixel_hash(x, y);
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let dimension = self.dimension;
+        self.dimension += 1;
+        if dimension >= PRIMES.len() {
+            return random_double();
+        }
+        let offset = to_unit(hash_combine(self.pixel, dimension as u32));
+        (radical_inverse(PRIMES[dimension], self.index) + offset).fract()
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.get_1d(), self.get_1d())
+    }
+}
+
+fn radical_inverse(base: u32, mut index: u32) -> f64 {
+    let inv_base = 1.0 / base as f64;
+    let mut inv_base_n = 1.0;
+    let mut reversed = 0.0;
+    while index > 0 {
+        let digit = index % base;
+        index /= base;
+        inv_base_n *= inv_base;
+        reversed += digit as f64 * inv_base_n;
+    }
+    reversed
+}
+
+// First two Sobol dimensions with Owen scrambling. Each 2D dimension pair
+// gets its own shuffled sample index so higher dimensions stay decorrelated
+// (Burley 2020, "Practical Hash-based Owen Scrambling").
+#[derive(Default)]
+pub struct SobolSampler {
+    pixel: u32,
+    index: u32,
+    dimension: u32,
+}
+
+impl SobolSampler {
+    fn sample(&mut self) -> (u32, u32) {
+        self.dimension += 1;
+        let seed = hash_combine(self.pixel, self.dimension);
+        let index = nested_uniform_scramble(self.index, seed);
+        let seed = hash(seed);
+        (
+            nested_uniform_scramble(index.reverse_bits(), hash_combine(seed, 0)),
+            nested_uniform_scramble(sobol_second_dimension(index), hash_combine(seed, 1)),
+        )
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_sample(&mut self, x: usize, y: usize, index: u32) {
+        self.pixel = pixel_hash(x, y);
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        to_unit(self.sample().0)
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let (x, y) = self.sample();
+        (to_unit(x), to_unit(y))
+    }
+}
+
+fn sobol_second_dimension(mut index: u32) -> u32 {
+    let mut v = 1 << 31;
+    let mut result = 0;
+    while index != 0 {
+        if index & 1 != 0 {
+            result ^= v;
+        }
+        index >>= 1;
+        v ^= v >> 1;
+    }
+    result
+}
+
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    let mut x = x.reverse_bits();
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x.reverse_bits()
+}
+
+const BLUE_NOISE_SIZE: usize = 64;
+
+// Blue noise tile offset per dimension. Successive samples of a pixel are
+// shifted along the golden ratio sequence in 1D and the R2 sequence, built on
+// the plastic constant, in 2D so they stay well spread over the square.
+#[derive(Default)]
+pub struct BlueNoiseSampler {
+    x: usize,
+    y: usize,
+    index: u32,
+    dimension: u32,
+}
+
+impl BlueNoiseSampler {
+    fn tile_value(&mut self) -> f64 {
+        self.dimension += 1;
+        let offset = hash(self.dimension) as usize;
+        let tx = (self.x + offset) % BLUE_NOISE_SIZE;
+        let ty = (self.y + (offset >> 8)) % BLUE_NOISE_SIZE;
+        blue_noise_tile()[ty * BLUE_NOISE_SIZE + tx]
+    }
+}
+
+impl Sampler for BlueNoiseSampler {
+    fn start_sample(&mut self, x: usize, y: usize, index: u32) {
+        self.x = x;
+        self.y = y;
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        const GOLDEN_RATIO_CONJUGATE: f64 = 0.618_033_988_749_894_9;
+
+        (self.tile_value() + self.index as f64 * GOLDEN_RATIO_CONJUGATE).fract()
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        // Reciprocals of the plastic constant and its square
+        const R2: (f64, f64) = (0.754_877_666_246_692_7, 0.569_840_290_998_053_3);
+
+        let (u, v) = (self.tile_value(), self.tile_value());
+        let index = self.index as f64;
+        ((u + index * R2.0).fract(), (v + index * R2.1).fract())
+    }
+}
+
+fn blue_noise_tile() -> &'static [f64] {
+    static TILE: OnceLock<Vec<f64>> = OnceLock::new();
+    TILE.get_or_init(void_and_cluster)
+}
+
+// Ulichney's void-and-cluster method on a toroidal tile, returning each
+// pixel's rank scaled to [0, 1)
+fn void_and_cluster() -> Vec<f64> {
+    const N: usize = BLUE_NOISE_SIZE;
+    const SIGMA: f64 = 1.5;
+
+    let mut kernel = vec![0.0; N * N];
+    for dy in 0..N {
+        for dx in 0..N {
+            let wx = dx.min(N - dx) as f64;
+            let wy = dy.min(N - dy) as f64;
+            kernel[dy * N + dx] = f64::exp(-(wx * wx + wy * wy) / (2.0 * SIGMA * SIGMA));
+        }
+    }
+
+    let update = |energy: &mut [f64], p: usize, sign: f64| {
+        let (px, py) = (p % N, p / N);
+        for y in 0..N {
+            for x in 0..N {
+                let k = ((y + N - py) % N) * N + (x + N - px) % N;
+                energy[y * N + x] += sign * kernel[k];
+            }
+        }
+    };
+    let tightest_cluster = |pattern: &[bool], energy: &[f64]| {
+        (0..N * N)
+            .filter(|&i| pattern[i])
+            .max_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+            .unwrap()
+    };
+    let largest_void = |pattern: &[bool], energy: &[f64]| {
+        (0..N * N)
+            .filter(|&i| !pattern[i])
+            .min_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+            .unwrap()
+    };
+
+    // Initial binary pattern, relaxed until the tightest cluster is the largest void
+    let mut pattern = vec![false; N * N];
+    let mut energy = vec![0.0; N * N];
+    let initial = N * N / 10;
+    let mut placed = 0;
+    let mut seed = 0;
+    while placed < initial {
+        seed += 1;
+        let p = hash(seed) as usize % (N * N);
+        if !pattern[p] {
+            pattern[p] = true;
+            update(&mut energy, p, 1.0);
+            placed += 1;
+        }
+    }
+    loop {
+        let cluster = tightest_cluster(&pattern, &energy);
+        pattern[cluster] = false;
+        update(&mut energy, cluster, -1.0);
+        let void = largest_void(&pattern, &energy);
+        pattern[void] = true;
+        update(&mut energy, void, 1.0);
+        if void == cluster {
+            break;
+        }
+    }
+
+    let mut rank = vec![0; N * N];
+
+    // Phase 1: rank the initial points by removing tightest clusters
+    let mut phase_pattern = pattern.clone();
+    let mut phase_energy = energy.clone();
+    for r in (0..initial).rev() {
+        let cluster = tightest_cluster(&phase_pattern, &phase_energy);
+        phase_pattern[cluster] = false;
+        update(&mut phase_energy, cluster, -1.0);
+        rank[cluster] = r;
+    }
+
+    // Phases 2 and 3: fill the largest voids until the tile is full
+    for r in initial..N * N {
+        let void = largest_void(&pattern, &energy);
+        pattern[void] = true;
+        update(&mut energy, void, 1.0);
+        rank[void] = r;
+    }
+
+    rank.iter()
+        .map(|&r| (r as f64 + 0.5) / (N * N) as f64)
+        .collect()
+}
+
+fn pixel_hash(x: usize, y: usize) -> u32 {
+    hash_combine(hash(x as u32), y as u32)
+}
+
+fn hash(mut x: u32) -> u32 {
+    // lowbias32 integer hash
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846ca68b);
+    x ^= x >> 16;
+    x
+}
+
+fn hash_combine(seed: u32, v: u32) -> u32 {
+    hash(
+        seed ^ v
+            .wrapping_add(0x9e3779b9)
+            .wrapping_add(seed << 6)
+            .wrapping_add(seed >> 2),
+    )
+}
+
+fn to_unit(x: u32) -> f64 {
+    x as f64 / 4294967296.0
+}
+
+// Kensler's hash-based permutation of [0, l)
+fn permute(mut i: u32, l: u32, p: u32) -> u32 {
+    let mut w = l - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | p >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < l {
+            break;
+        }
+    }
+    (i.wrapping_add(p)) % l
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Stratum of each dimension for every sample of one pixel, 1D dimensions
+    // interleaved with 2D ones as a path would ask for them
+    fn strata(samples_per_pixel: u32, x: usize, y: usize) -> Vec<Vec<usize>> {
+        let mut sampler = StratifiedSampler::new(samples_per_pixel);
+        let n = samples_per_pixel as f64;
+        let grid = sampler.grid as f64;
+        let mut strata = vec![Vec::new(); 4];
+        for index in 0..samples_per_pixel {
+            sampler.start_sample(x, y, index);
+            let (u, v) = sampler.get_2d();
+            strata[0].push((v * grid) as usize * grid as usize + (u * grid) as usize);
+            strata[1].push((sampler.get_1d() * n) as usize);
+            let (u, v) = sampler.get_2d();
+            strata[2].push((v * grid) as usize * grid as usize + (u * grid) as usize);
+            strata[3].push((sampler.get_1d() * n) as usize);
+        }
+        strata
+    }
+
+    #[test]
+    fn stratified_fills_every_stratum_once() {
+        for (x, y) in [(0, 0), (17, 3), (250, 140)] {
+            for mut dimension in strata(16, x, y) {
+                dimension.sort();
+                assert_eq!(dimension, (0..16).collect::<Vec<usize>>());
+            }
+        }
+
+        // Sample counts that are not square still stratify 1D dimensions fully
+        let strata = strata(10, 5, 5);
+        for dimension in [&strata[1], &strata[3]] {
+            let mut dimension = dimension.clone();
+            dimension.sort();
+            assert_eq!(dimension, (0..10).collect::<Vec<usize>>());
+        }
+    }
+
+    #[test]
+    fn low_discrepancy_samples_stay_in_unit_interval() {
+        for kind in [SamplerKind::Halton, SamplerKind::Sobol] {
+            let mut sampler = kind.create(64);
+            for (x, y) in [(0, 0), (1, 0), (63, 17), (299, 168)] {
+                for index in 0..64 {
+                    sampler.start_sample(x, y, index);
+                    for _ in 0..20 {
+                        let (u, v) = sampler.get_2d();
+                        for value in [u, v, sampler.get_1d()] {
+                            assert!(
+                                (0.0..1.0).contains(&value),
+                                "{} gave {}",
+                                kind.name(),
+                                value
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Points falling on a line or clumping would leave strata of the square
+    // empty, a well spread set puts close to the average in every one
+    #[test]
+    fn blue_noise_covers_the_square() {
+        let mut sampler = BlueNoiseSampler::default();
+        for (x, y) in [(0, 0), (5, 9), (40, 63)] {
+            for dimension in 0..4 {
+                let mut counts = [0; 16];
+                for index in 0..64 {
+                    sampler.start_sample(x, y, index);
+                    let mut point = sampler.get_2d();
+                    for _ in 0..dimension {
+                        point = sampler.get_2d();
+                    }
+                    counts[(point.1 * 4.0) as usize * 4 + (point.0 * 4.0) as usize] += 1;
+                }
+                assert!(
+                    counts.iter().all(|&count| (2..=6).contains(&count)),
+                    "pixel ({}, {}) dimension {}: {:?}",
+                    x,
+                    y,
+                    dimension,
+                    counts
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,640 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::rc::Rc;
+
+use crate::checkpoint::invalid_data;
+use crate::colour::Colour;
+use crate::common;
+use crate::hittable::HittableList;
+use crate::material::{absorption_from_transmittance, Coated, Conductor, Dialectric, Lambertian};
+use crate::material::{Material, Principled, RoughDielectric};
+use crate::material::{OrenNayar, Subsurface};
+use crate::spectrum::{self, Dispersion};
+use crate::sphere::Sphere;
+use crate::thin_film::ThinFilm;
+use crate::vec3::Point3;
+
+// Plain text world description, one object per line:
+//
+//   sphere <x> <y> <z> <radius> <material>
+//   moving_sphere <x0> <y0> <z0> <x1> <y1> <z1> <radius> <material>
+//
+// where the material is one of
+//
+//   lambertian <r> <g> <b>
+//   oren_nayar <r> <g> <b> <sigma>
+//   metal <r> <g> <b> <roughness>
+//   plastic <r> <g> <b> <coat refraction index> <coat roughness>
+//   subsurface <r> <g> <b> <mean free path r g b> <anisotropy> <refraction index>
+//   conductor <gold|copper|aluminium|silver> <roughness> [<roughness y>]
+//   conductor <eta r g b> <k r g b> <roughness> [<roughness y>]
+//   dielectric <refraction index> [<roughness>] [absorb <r> <g> <b>]
+//   dielectric <refraction index> [<roughness>] [tint <r> <g> <b> <distance>]
+//
+// and a smooth dielectric can take a dispersive refraction index instead of
+// a number, used in spectral mode:
+//
+//   cauchy <a> <b>
+//   sellmeier <b1> <b2> <b3> <c1> <c2> <c3>
+//   bk7 | diamond
+//
+// Metals, conductors and smooth dielectrics can end with a thin film coating
+// for iridescence, its thickness in nanometres and its refraction index:
+//
+//   ... film <thickness> <refraction index>
+//
+// The principled material takes a base colour followed by any of its other
+// parameters by name, the rest keep their defaults:
+//
+//   principled <r> <g> <b> [metallic <v>] [roughness <v>] [specular <v>]
+//       [specular_tint <v>] [sheen <v>] [clearcoat <v>] [clearcoat_gloss <v>]
+//       [transmission <v>] [ior <v>]
+//
+// `metal` is a conductor given by its reflectance at normal incidence, a
+// second roughness makes a conductor anisotropic with the first along the
+// surface tangent, around the vertical axis on a sphere. A dielectric with a
+// roughness is frosted glass. `absorb` gives the Beer-Lambert coefficient of
+// tinted glass directly, `tint` the colour left after travelling `distance`
+// through it.
+//
+// Blank lines and lines starting with # are ignored. Numbers are written
+// with full precision so a saved scene builds the identical world.
+#[derive(Clone, Debug)]
+pub enum MaterialDesc {
+    Lambertian {
+        albedo: Colour,
+    },
+    OrenNayar {
+        albedo: Colour,
+        sigma: f64,
+    },
+    Metal {
+        reflectance: Colour,
+        roughness: f64,
+        film: Option<ThinFilm>,
+    },
+    Conductor {
+        eta: Colour,
+        k: Colour,
+        roughness_x: f64,
+        roughness_y: f64,
+        film: Option<ThinFilm>,
+    },
+    Dielectric {
+        refraction_index: f64,
+        roughness: f64,
+        absorption: Colour,
+        dispersion: Option<Dispersion>,
+        film: Option<ThinFilm>,
+    },
+    Plastic {
+        albedo: Colour,
+        refraction_index: f64,
+        roughness: f64,
+    },
+    Subsurface {
+        albedo: Colour,
+        mean_free_path: Colour,
+        anisotropy: f64,
+        refraction_index: f64,
+    },
+    Principled(Principled),
+}
+
+impl MaterialDesc {
+    fn build(&self) -> Rc<dyn Material> {
+        match *self {
+            MaterialDesc::Lambertian { albedo } => Rc::new(Lambertian::new(albedo)),
+            MaterialDesc::OrenNayar { albedo, sigma } => Rc::new(OrenNayar::new(albedo, sigma)),
+            MaterialDesc::Metal {
+                reflectance,
+                roughness,
+                film,
+            } => {
+                let mut conductor = Conductor::from_reflectance(reflectance, roughness);
+                conductor.set_thin_film(film);
+                Rc::new(conductor)
+            }
+            MaterialDesc::Conductor {
+                eta,
+                k,
+                roughness_x,
+                roughness_y,
+                film,
+            } => {
+                let mut conductor = Conductor::anisotropic(eta, k, roughness_x, roughness_y);
+                conductor.set_thin_film(film);
+                Rc::new(conductor)
+            }
+            MaterialDesc::Plastic {
+                albedo,
+                refraction_index,
+                roughness,
+            } => Rc::new(Coated::new(albedo, refraction_index, roughness)),
+            MaterialDesc::Subsurface {
+                albedo,
+                mean_free_path,
+                anisotropy,
+                refraction_index,
+            } => Rc::new(Subsurface::new(
+                albedo,
+                mean_free_path,
+                anisotropy,
+                refraction_index,
+            )),
+            MaterialDesc::Principled(principled) => Rc::new(principled),
+            MaterialDesc::Dielectric {
+                refraction_index,
+                roughness,
+                absorption,
+                dispersion,
+                film,
+            } => {
+                if roughness > 0.0 {
+                    return Rc::new(RoughDielectric::absorbing(
+                        refraction_index,
+                        roughness,
+                        absorption,
+                    ));
+                }
+                let mut dielectric = match dispersion {
+                    Some(dispersion) => Dialectric::dispersive(dispersion, absorption),
+                    None => Dialectric::absorbing(refraction_index, absorption),
+                };
+                dielectric.set_thin_film(film);
+                Rc::new(dielectric)
+            }
+        }
+    }
+
+    fn parse(words: &[&str]) -> Result<MaterialDesc, String> {
+        let (words, film) = match words.iter().position(|w| *w == "film") {
+            Some(index) => match parse_numbers(&words[index + 1..])?[..] {
+                [thickness, refractive_index] => (
+                    &words[..index],
+                    Some(ThinFilm::new(thickness, refractive_index)),
+                ),
+                _ => {
+                    return Err(String::from(
+                        "film needs a thickness and a refraction index",
+                    ))
+                }
+            },
+            None => (words, None),
+        };
+        let mut material = MaterialDesc::parse_material(words)?;
+        if let Some(new_film) = film {
+            match &mut material {
+                MaterialDesc::Metal { film, .. } | MaterialDesc::Conductor { film, .. } => {
+                    *film = Some(new_film)
+                }
+                MaterialDesc::Dielectric {
+                    film, roughness, ..
+                } if *roughness == 0.0 => *film = Some(new_film),
+                _ => {
+                    return Err(String::from(
+                        "films need a metal, conductor or smooth dielectric",
+                    ))
+                }
+            }
+        }
+        Ok(material)
+    }
+
+    fn parse_material(words: &[&str]) -> Result<MaterialDesc, String> {
+        let (&name, values) = words.split_first().ok_or("missing material")?;
+        match name {
+            "conductor" => return MaterialDesc::parse_conductor(values),
+            "dielectric" => return MaterialDesc::parse_dielectric(values),
+            "principled" => return MaterialDesc::parse_principled(values),
+            _ => {}
+        }
+        let values = parse_numbers(values)?;
+        match (name, values.as_slice()) {
+            ("lambertian", &[r, g, b]) => Ok(MaterialDesc::Lambertian {
+                albedo: Colour::new(r, g, b),
+            }),
+            ("oren_nayar", &[r, g, b, sigma]) => Ok(MaterialDesc::OrenNayar {
+                albedo: Colour::new(r, g, b),
+                sigma,
+            }),
+            ("metal", &[r, g, b, roughness]) => Ok(MaterialDesc::Metal {
+                reflectance: Colour::new(r, g, b),
+                roughness,
+                film: None,
+            }),
+            ("plastic", &[r, g, b, refraction_index, roughness]) => Ok(MaterialDesc::Plastic {
+                albedo: Colour::new(r, g, b),
+                refraction_index,
+                roughness,
+            }),
+            ("subsurface", &[r, g, b, mr, mg, mb, anisotropy, refraction_index]) => {
+                if mr <= 0.0 || mg <= 0.0 || mb <= 0.0 {
+                    return Err(String::from("mean free path must be positive"));
+                }
+                Ok(MaterialDesc::Subsurface {
+                    albedo: Colour::new(r, g, b),
+                    mean_free_path: Colour::new(mr, mg, mb),
+                    anisotropy,
+                    refraction_index,
+                })
+            }
+            ("lambertian" | "oren_nayar" | "metal" | "plastic" | "subsurface", _) => {
+                Err(format!("wrong number of values for {}", name))
+            }
+            _ => Err(format!("unknown material {}", name)),
+        }
+    }
+
+    fn parse_conductor(words: &[&str]) -> Result<MaterialDesc, String> {
+        let (eta, k, roughness) = match words.first().and_then(|w| Conductor::preset_ior(w)) {
+            Some((eta, k)) => (eta, k, parse_numbers(&words[1..])?),
+            None => {
+                let values = parse_numbers(words)?;
+                if values.len() < 6 {
+                    return Err(String::from("too few values for conductor"));
+                }
+                let eta = Colour::new(values[0], values[1], values[2]);
+                let k = Colour::new(values[3], values[4], values[5]);
+                (eta, k, values[6..].to_vec())
+            }
+        };
+        let (roughness_x, roughness_y) = match roughness[..] {
+            [roughness] => (roughness, roughness),
+            [roughness_x, roughness_y] => (roughness_x, roughness_y),
+            _ => return Err(String::from("conductor needs one or two roughness values")),
+        };
+        Ok(MaterialDesc::Conductor {
+            eta,
+            k,
+            roughness_x,
+            roughness_y,
+            film: None,
+        })
+    }
+
+    fn parse_dielectric(words: &[&str]) -> Result<MaterialDesc, String> {
+        let split = words
+            .iter()
+            .position(|w| *w == "absorb" || *w == "tint")
+            .unwrap_or(words.len());
+        let (dispersion, rest) = MaterialDesc::parse_dispersion(&words[..split])?;
+        let (refraction_index, roughness) = match (dispersion, &parse_numbers(rest)?[..]) {
+            (None, &[refraction_index]) => (refraction_index, 0.0),
+            (None, &[refraction_index, roughness]) => (refraction_index, roughness),
+            (Some(dispersion), &[]) => (dispersion.refractive_index(spectrum::LAMBDA_D), 0.0),
+            (Some(_), &[_]) => return Err(String::from("dispersive dielectrics must be smooth")),
+            _ => return Err(String::from("wrong number of values for dielectric")),
+        };
+        let absorption = match (
+            words.get(split),
+            &parse_numbers(&words[(split + 1).min(words.len())..])?[..],
+        ) {
+            (None, _) => Colour::default(),
+            (Some(&"absorb"), &[r, g, b]) => Colour::new(r, g, b),
+            (Some(&"tint"), &[r, g, b, distance]) => {
+                absorption_from_transmittance(Colour::new(r, g, b), distance)
+            }
+            (Some(keyword), _) => return Err(format!("wrong number of values for {}", keyword)),
+        };
+        Ok(MaterialDesc::Dielectric {
+            refraction_index,
+            roughness,
+            absorption,
+            dispersion,
+            film: None,
+        })
+    }
+
+    fn parse_principled(words: &[&str]) -> Result<MaterialDesc, String> {
+        if words.len() < 3 {
+            return Err(String::from("too few values for principled"));
+        }
+        let base = parse_numbers(&words[..3])?;
+        let mut principled = Principled::new(Colour::new(base[0], base[1], base[2]));
+        for pair in words[3..].chunks(2) {
+            let [name, value] = pair else {
+                return Err(format!("missing value for {}", pair[0]));
+            };
+            let value = parse_numbers(&[value])?[0];
+            let parameter = match *name {
+                "metallic" => &mut principled.metallic,
+                "roughness" => &mut principled.roughness,
+                "specular" => &mut principled.specular,
+                "specular_tint" => &mut principled.specular_tint,
+                "sheen" => &mut principled.sheen,
+                "clearcoat" => &mut principled.clearcoat,
+                "clearcoat_gloss" => &mut principled.clearcoat_gloss,
+                "transmission" => &mut principled.transmission,
+                "ior" => &mut principled.refractive_index,
+                _ => return Err(format!("unknown principled parameter {}", name)),
+            };
+            *parameter = value;
+        }
+        Ok(MaterialDesc::Principled(principled))
+    }
+
+    fn parse_dispersion<'a>(
+        words: &'a [&'a str],
+    ) -> Result<(Option<Dispersion>, &'a [&'a str]), String> {
+        let count = match words.first() {
+            Some(&"cauchy") => 2,
+            Some(&"sellmeier") => 6,
+            Some(name) => match Dispersion::preset(name) {
+                Some(dispersion) => return Ok((Some(dispersion), &words[1..])),
+                None => return Ok((None, words)),
+            },
+            None => return Ok((None, words)),
+        };
+        if words.len() <= count {
+            return Err(format!("too few values for {}", words[0]));
+        }
+        let values = parse_numbers(&words[1..=count])?;
+        let dispersion = match values[..] {
+            [a, b] => Dispersion::Cauchy { a, b },
+            [b1, b2, b3, c1, c2, c3] => Dispersion::Sellmeier {
+                b: [b1, b2, b3],
+                c: [c1, c2, c3],
+            },
+            _ => unreachable!(),
+        };
+        Ok((Some(dispersion), &words[count + 1..]))
+    }
+
+    fn write(&self, out: &mut String) {
+        match *self {
+            MaterialDesc::Lambertian { albedo } => {
+                write!(
+                    out,
+                    "lambertian {} {} {}",
+                    albedo.x(),
+                    albedo.y(),
+                    albedo.z()
+                )
+            }
+            MaterialDesc::OrenNayar { albedo, sigma } => write!(
+                out,
+                "oren_nayar {} {} {} {}",
+                albedo.x(),
+                albedo.y(),
+                albedo.z(),
+                sigma
+            ),
+            MaterialDesc::Metal {
+                reflectance,
+                roughness,
+                ..
+            } => write!(
+                out,
+                "metal {} {} {} {}",
+                reflectance.x(),
+                reflectance.y(),
+                reflectance.z(),
+                roughness
+            ),
+            MaterialDesc::Conductor {
+                eta,
+                k,
+                roughness_x,
+                roughness_y,
+                ..
+            } => write!(
+                out,
+                "conductor {} {} {} {} {} {} {} {}",
+                eta.x(),
+                eta.y(),
+                eta.z(),
+                k.x(),
+                k.y(),
+                k.z(),
+                roughness_x,
+                roughness_y
+            ),
+            MaterialDesc::Plastic {
+                albedo,
+                refraction_index,
+                roughness,
+            } => write!(
+                out,
+                "plastic {} {} {} {} {}",
+                albedo.x(),
+                albedo.y(),
+                albedo.z(),
+                refraction_index,
+                roughness
+            ),
+            MaterialDesc::Subsurface {
+                albedo,
+                mean_free_path,
+                anisotropy,
+                refraction_index,
+            } => write!(
+                out,
+                "subsurface {} {} {} {} {} {} {} {}",
+                albedo.x(),
+                albedo.y(),
+                albedo.z(),
+                mean_free_path.x(),
+                mean_free_path.y(),
+                mean_free_path.z(),
+                anisotropy,
+                refraction_index
+            ),
+            MaterialDesc::Principled(p) => write!(
+                out,
+                "principled {} {} {} metallic {} roughness {} specular {} specular_tint {} \
+                 sheen {} clearcoat {} clearcoat_gloss {} transmission {} ior {}",
+                p.base_colour.x(),
+                p.base_colour.y(),
+                p.base_colour.z(),
+                p.metallic,
+                p.roughness,
+                p.specular,
+                p.specular_tint,
+                p.sheen,
+                p.clearcoat,
+                p.clearcoat_gloss,
+                p.transmission,
+                p.refractive_index
+            ),
+            MaterialDesc::Dielectric {
+                refraction_index,
+                roughness,
+                absorption,
+                dispersion,
+                ..
+            } => {
+                match dispersion {
+                    None => write!(out, "dielectric {}", refraction_index),
+                    Some(Dispersion::Cauchy { a, b }) => {
+                        write!(out, "dielectric cauchy {} {}", a, b)
+                    }
+                    Some(Dispersion::Sellmeier { b, c }) => write!(
+                        out,
+                        "dielectric sellmeier {} {} {} {} {} {}",
+                        b[0], b[1], b[2], c[0], c[1], c[2]
+                    ),
+                }
+                .unwrap();
+                if roughness > 0.0 {
+                    write!(out, " {}", roughness).unwrap();
+                }
+                if absorption != Colour::default() {
+                    write!(
+                        out,
+                        " absorb {} {} {}",
+                        absorption.x(),
+                        absorption.y(),
+                        absorption.z()
+                    )
+                    .unwrap();
+                }
+                Ok(())
+            }
+        }
+        .unwrap();
+        if let Some(film) = self.film() {
+            write!(out, " film {} {}", film.thickness, film.refractive_index).unwrap();
+        }
+    }
+
+    fn film(&self) -> Option<ThinFilm> {
+        match *self {
+            MaterialDesc::Metal { film, .. }
+            | MaterialDesc::Conductor { film, .. }
+            | MaterialDesc::Dielectric { film, .. } => film,
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct SphereDesc {
+    centre1: Point3,
+    centre2: Point3,
+    radius: f64,
+    material: MaterialDesc,
+}
+
+#[derive(Clone, Default, Debug)]
+pub struct Scene {
+    spheres: Vec<SphereDesc>,
+}
+
+impl Scene {
+    pub fn new() -> Scene {
+        Default::default()
+    }
+
+    pub fn add_sphere(&mut self, centre: Point3, radius: f64, material: MaterialDesc) {
+        self.add_moving_sphere(centre, centre, radius, material);
+    }
+
+    pub fn add_moving_sphere(
+        &mut self,
+        centre1: Point3,
+        centre2: Point3,
+        radius: f64,
+        material: MaterialDesc,
+    ) {
+        self.spheres.push(SphereDesc {
+            centre1,
+            centre2,
+            radius,
+            material,
+        });
+    }
+
+    pub fn build(&self) -> HittableList {
+        let mut world = HittableList::new();
+        for sphere in &self.spheres {
+            let material = sphere.material.build();
+            world.add(Box::new(Sphere::new_moving(
+                sphere.centre1,
+                sphere.centre2,
+                sphere.radius,
+                material,
+            )));
+        }
+        world
+    }
+
+    pub fn load(path: &str) -> io::Result<Scene> {
+        Scene::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> io::Result<Scene> {
+        let mut scene = Scene::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            scene
+                .parse_object(line)
+                .map_err(|message| invalid_data(&format!("line {}: {}", index + 1, message)))?;
+        }
+        Ok(scene)
+    }
+
+    fn parse_object(&mut self, line: &str) -> Result<(), String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (centres, rest) = match words[0] {
+            "sphere" if words.len() > 4 => (1, &words[4..]),
+            "moving_sphere" if words.len() > 7 => (2, &words[7..]),
+            "sphere" | "moving_sphere" => return Err(format!("too few values for {}", words[0])),
+            name => return Err(format!("unknown object {}", name)),
+        };
+        let values = parse_numbers(&words[1..1 + 3 * centres])?;
+        let centre1 = Point3::new(values[0], values[1], values[2]);
+        let centre2 = if centres == 2 {
+            Point3::new(values[3], values[4], values[5])
+        } else {
+            centre1
+        };
+        let radius = parse_numbers(&rest[..1])?[0];
+        let material = MaterialDesc::parse(&rest[1..])?;
+        self.add_moving_sphere(centre1, centre2, radius, material);
+        Ok(())
+    }
+
+    // Identifies the scene in saved renders, the text format writes every
+    // value exactly
+    pub fn fingerprint(&self) -> u64 {
+        common::hash_bytes(self.to_text().as_bytes())
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        for sphere in &self.spheres {
+            let (c1, c2) = (sphere.centre1, sphere.centre2);
+            if c1 == c2 {
+                write!(out, "sphere {} {} {} ", c1.x(), c1.y(), c1.z()).unwrap();
+            } else {
+                write!(
+                    out,
+                    "moving_sphere {} {} {} {} {} {} ",
+                    c1.x(),
+                    c1.y(),
+                    c1.z(),
+                    c2.x(),
+                    c2.y(),
+                    c2.z()
+                )
+                .unwrap();
+            }
+            write!(out, "{} ", sphere.radius).unwrap();
+            sphere.material.write(&mut out);
+            out.push('\n');
+        }
+        out
+    }
+}
+
+fn parse_numbers(words: &[&str]) -> Result<Vec<f64>, String> {
+    words
+        .iter()
+        .map(|word| word.parse().map_err(|_| format!("invalid number {}", word)))
+        .collect()
+}
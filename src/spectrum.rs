@@ -0,0 +1,193 @@
+use std::sync::OnceLock;
+
+use crate::colour::Colour;
+use crate::vec3::Vec3;
+
+// Visible range sampled in spectral mode, in nanometres
+pub const LAMBDA_MIN: f64 = 380.0;
+pub const LAMBDA_MAX: f64 = 780.0;
+
+// Sodium D line, where a refractive index is usually quoted
+pub const LAMBDA_D: f64 = 589.3;
+
+// Wavelengths carried along one path. The hero wavelength is sampled
+// uniformly and the others are spaced evenly after it, wrapping around the
+// visible range (Wilkie et al. 2014). There are three so a spectral sample
+// fits in a `Colour` and the path tracer works on both unchanged.
+#[derive(Clone, Copy, Debug)]
+pub struct SampledWavelengths {
+    lambda: [f64; 3],
+    pdf: [f64; 3],
+}
+
+impl SampledWavelengths {
+    pub fn sample(u: f64) -> SampledWavelengths {
+        let range = LAMBDA_MAX - LAMBDA_MIN;
+        let mut lambda = [0.0; 3];
+        for (i, l) in lambda.iter_mut().enumerate() {
+            *l = LAMBDA_MIN + (u * range + i as f64 * range / 3.0) % range;
+        }
+        SampledWavelengths {
+            lambda,
+            pdf: [1.0 / range; 3],
+        }
+    }
+
+    pub fn hero(&self) -> f64 {
+        self.lambda[0]
+    }
+
+    // After a wavelength dependent refraction the other wavelengths would
+    // have gone another way, so only the hero carries on for all of them
+    pub fn terminate_secondary(&mut self) {
+        if self.pdf[1] == 0.0 {
+            return;
+        }
+        self.pdf = [self.pdf[0] / 3.0, 0.0, 0.0];
+    }
+
+    // Reflectance or radiance of an RGB colour at each wavelength
+    pub fn upsample(&self, rgb: Colour) -> Vec3 {
+        let [a, b, c] = self.lambda.map(|l| rgb_to_spectrum(rgb, l));
+        Vec3::new(a, b, c)
+    }
+
+    // Monte Carlo estimate of the CIE XYZ integral of the sampled radiance,
+    // returned as linear sRGB
+    pub fn to_rgb(&self, values: Vec3) -> Colour {
+        let mut xyz = Vec3::default();
+        for (i, value) in [values.x(), values.y(), values.z()].into_iter().enumerate() {
+            if self.pdf[i] > 0.0 {
+                xyz += value / self.pdf[i] * cie_xyz(self.lambda[i]);
+            }
+        }
+        let rgb = xyz_to_srgb(xyz / 3.0);
+        let white = white_balance();
+        Colour::new(
+            rgb.x() / white.x(),
+            rgb.y() / white.y(),
+            rgb.z() / white.z(),
+        )
+    }
+}
+
+// Linear sRGB of a reflectance spectrum under the equal energy white, for
+// wavelength dependent effects in RGB mode
+pub fn reflectance_to_rgb(reflectance: impl Fn(f64) -> f64) -> Colour {
+    let steps = 40;
+    let step = (LAMBDA_MAX - LAMBDA_MIN) / steps as f64;
+    let mut xyz = Vec3::default();
+    for i in 0..steps {
+        let lambda = LAMBDA_MIN + (i as f64 + 0.5) * step;
+        xyz += step * reflectance(lambda) * cie_xyz(lambda);
+    }
+    let rgb = xyz_to_srgb(xyz);
+    let white = white_balance();
+    Colour::new(
+        rgb.x() / white.x(),
+        rgb.y() / white.y(),
+        rgb.z() / white.z(),
+    )
+}
+
+// Value of an RGB quantity such as a conductor's index at a wavelength,
+// taking the channels to be at 650, 550 and 450nm as the presets are
+pub fn rgb_at_wavelength(rgb: Colour, lambda: f64) -> f64 {
+    let t = ((lambda - 450.0) / 100.0).clamp(0.0, 2.0);
+    if t < 1.0 {
+        rgb.z() + t * (rgb.y() - rgb.z())
+    } else {
+        rgb.y() + (t - 1.0) * (rgb.x() - rgb.y())
+    }
+}
+
+// Smooth partition of unity over the visible range, so white and greys turn
+// into flat spectra, every reflectance stays within [0, 1] and the primaries
+// come back out close to themselves
+fn rgb_to_spectrum(rgb: Colour, lambda: f64) -> f64 {
+    let weight = |centre: f64| f64::exp(-((lambda - centre) / 40.0).powi(2));
+    let (r, g, b) = (weight(630.0), weight(540.0), weight(440.0));
+    (rgb.x() * r + rgb.y() * g + rgb.z() * b) / (r + g + b)
+}
+
+// CIE 1931 colour matching functions, multi-lobe fit by Wyman et al. 2013
+pub fn cie_xyz(lambda: f64) -> Vec3 {
+    let g = |mu: f64, sigma_below: f64, sigma_above: f64| {
+        let sigma = if lambda < mu {
+            sigma_below
+        } else {
+            sigma_above
+        };
+        f64::exp(-0.5 * ((lambda - mu) / sigma).powi(2))
+    };
+    Vec3::new(
+        1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2),
+        0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1),
+        1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8),
+    )
+}
+
+fn xyz_to_srgb(xyz: Vec3) -> Colour {
+    let (x, y, z) = (xyz.x(), xyz.y(), xyz.z());
+    Colour::new(
+        3.2404542 * x - 1.5371385 * y - 0.4985314 * z,
+        -0.9692660 * x + 1.8760108 * y + 0.0415560 * z,
+        0.0556434 * x - 0.2040259 * y + 1.0572252 * z,
+    )
+}
+
+// sRGB of a flat spectrum, dividing by it makes the equal energy white
+// come out as (1, 1, 1) so RGB and spectral renders match on grey scenes
+fn white_balance() -> Colour {
+    static WHITE: OnceLock<Colour> = OnceLock::new();
+    *WHITE.get_or_init(|| {
+        let steps = 4000;
+        let step = (LAMBDA_MAX - LAMBDA_MIN) / steps as f64;
+        let mut xyz = Vec3::default();
+        for i in 0..steps {
+            xyz += step * cie_xyz(LAMBDA_MIN + (i as f64 + 0.5) * step);
+        }
+        xyz_to_srgb(xyz)
+    })
+}
+
+// Wavelength dependent refractive index of a dispersive dielectric
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Dispersion {
+    // n = a + b / lambda^2, lambda in micrometres
+    Cauchy { a: f64, b: f64 },
+    // n^2 = 1 + sum b_i lambda^2 / (lambda^2 - c_i), lambda in micrometres
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl Dispersion {
+    // Schott N-BK7 crown glass
+    pub const BK7: Dispersion = Dispersion::Sellmeier {
+        b: [1.03961212, 0.231792344, 1.01046945],
+        c: [0.00600069867, 0.0200179144, 103.560653],
+    };
+
+    pub const DIAMOND: Dispersion = Dispersion::Sellmeier {
+        b: [0.3306, 4.3356, 0.0],
+        c: [0.1750 * 0.1750, 0.1060 * 0.1060, 0.0],
+    };
+
+    pub fn preset(name: &str) -> Option<Dispersion> {
+        match name {
+            "bk7" => Some(Dispersion::BK7),
+            "diamond" => Some(Dispersion::DIAMOND),
+            _ => None,
+        }
+    }
+
+    pub fn refractive_index(&self, lambda: f64) -> f64 {
+        let l2 = (lambda / 1000.0).powi(2);
+        match *self {
+            Dispersion::Cauchy { a, b } => a + b / l2,
+            Dispersion::Sellmeier { b, c } => {
+                let sum: f64 = b.iter().zip(c).map(|(b, c)| b * l2 / (l2 - c)).sum();
+                (1.0 + sum).sqrt()
+            }
+        }
+    }
+}
@@ -3,7 +3,8 @@ use std::rc::Rc;
 use crate::hittable::{HitRecord, Hittable};
 use crate::material::Material;
 use crate::ray::Ray;
-use crate::vec3::Point3;
+use crate::stats::{self, Primitive};
+use crate::vec3::{Point3, Vec3};
 
 pub struct Sphere {
     centre1: Point3,
@@ -39,6 +40,7 @@ impl Sphere {
 
 impl Hittable for Sphere {
     fn hit(&self, ray: &Ray, ray_tmin: f64, ray_tmax: f64, rec: &mut HitRecord) -> bool {
+        stats::count_hit(Primitive::Sphere);
         let oc = self.centre(ray.tm) - ray.origin;
         let a = ray.direction.length_squared();
         let h = ray.direction.dot(&oc);
@@ -63,7 +65,9 @@ impl Hittable for Sphere {
         rec.t = root;
         rec.p = ray.at(root);
         let outward_normal = (rec.p - self.centre(ray.tm)) / self.radius;
-        rec.set_face_normal(&ray, outward_normal);
+        rec.set_face_normal(ray, outward_normal);
+        // dP/du with u the longitude around the vertical axis, vanishing at the poles
+        rec.tangent = Vec3::new(outward_normal.z(), 0.0, -outward_normal.x());
         rec.mat = Some(self.mat.clone());
         true
     }
@@ -0,0 +1,217 @@
+use std::cell::Cell;
+use std::io::Write;
+use std::time::Duration;
+
+// Hittable types whose `hit` calls are counted
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Primitive {
+    Sphere,
+    List,
+}
+
+impl Primitive {
+    pub const ALL: [Primitive; 2] = [Primitive::Sphere, Primitive::List];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Primitive::Sphere => "sphere",
+            Primitive::List => "list",
+        }
+    }
+}
+
+// Counting costs about a third of the render time, so it is off unless a
+// render asks for it. While off `hit` only pays for loading the flag. Like
+// the counters it belongs to the thread, so renders on other threads never
+// switch it for this one.
+thread_local! {
+    static COUNTING: Cell<bool> = const { Cell::new(false) };
+    static HIT_CALLS: [Cell<u64>; Primitive::ALL.len()] = Default::default();
+}
+
+pub fn set_counting(counting: bool) {
+    COUNTING.with(|flag| flag.set(counting));
+}
+
+// Called at the top of every `Hittable::hit`
+#[inline]
+pub fn count_hit(primitive: Primitive) {
+    if !COUNTING.with(Cell::get) {
+        return;
+    }
+    HIT_CALLS.with(|calls| {
+        let counter = &calls[primitive as usize];
+        counter.set(counter.get() + 1);
+    });
+}
+
+// Running totals for this thread, the renderer takes differences around a pass
+pub fn hit_calls() -> [u64; Primitive::ALL.len()] {
+    HIT_CALLS.with(|calls| calls.each_ref().map(Cell::get))
+}
+
+#[derive(Clone, Default, Debug)]
+pub struct RenderStats {
+    pub primary_rays: u64,
+    pub secondary_rays: u64,
+    pub hit_calls: [u64; Primitive::ALL.len()],
+    pub paths: u64,
+    // Rays traced along all paths, for the average path length
+    pub path_segments: u64,
+    pub max_depth_terminations: u64,
+    // Random walks inside subsurface media cut off after too many scatterings
+    pub walk_terminations: u64,
+    pub absorbed: u64,
+    pub escaped: u64,
+    pub elapsed: Duration,
+}
+
+impl RenderStats {
+    pub fn rays(&self) -> u64 {
+        self.primary_rays + self.secondary_rays
+    }
+
+    pub fn hit_calls(&self, primitive: Primitive) -> u64 {
+        self.hit_calls[primitive as usize]
+    }
+
+    pub fn average_path_length(&self) -> f64 {
+        if self.paths == 0 {
+            return 0.0;
+        }
+        self.path_segments as f64 / self.paths as f64
+    }
+
+    pub fn rays_per_second(&self) -> f64 {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds > 0.0 {
+            self.rays() as f64 / seconds
+        } else {
+            0.0
+        }
+    }
+
+    pub fn write_summary(&self, out: &mut impl Write) {
+        let mut lines = vec![
+            format!("Render time:          {:.2}s", self.elapsed.as_secs_f64()),
+            format!("Primary rays:         {}", self.primary_rays),
+            format!("Secondary rays:       {}", self.secondary_rays),
+            format!(
+                "Rays per second:      {:.2}M",
+                self.rays_per_second() / 1.0e6
+            ),
+        ];
+        for primitive in Primitive::ALL {
+            lines.push(format!(
+                "{:<22}{}",
+                format!("Hit calls ({}):", primitive.name()),
+                self.hit_calls(primitive)
+            ));
+        }
+        lines.extend([
+            format!("Paths:                {}", self.paths),
+            format!("Average path length:  {:.3}", self.average_path_length()),
+            format!("Escaped:              {}", self.escaped),
+            format!("Absorbed:             {}", self.absorbed),
+            format!("Max depth reached:    {}", self.max_depth_terminations),
+            format!("Walks cut off:        {}", self.walk_terminations),
+        ]);
+        for line in lines {
+            writeln!(out, "{}", line).expect("Writing render statistics");
+        }
+    }
+
+    pub fn write_json(&self, out: &mut impl Write) {
+        let hit_calls = Primitive::ALL
+            .iter()
+            .map(|&p| format!("\"{}\":{}", p.name(), self.hit_calls(p)))
+            .collect::<Vec<String>>()
+            .join(",");
+        writeln!(
+            out,
+            "{{\"elapsed\":{},\"primary_rays\":{},\"secondary_rays\":{},\"rays_per_second\":{},\"hit_calls\":{{{}}},\"paths\":{},\"average_path_length\":{},\"escaped\":{},\"absorbed\":{},\"max_depth_terminations\":{},\"walk_terminations\":{}}}",
+            self.elapsed.as_secs_f64(),
+            self.primary_rays,
+            self.secondary_rays,
+            self.rays_per_second(),
+            hit_calls,
+            self.paths,
+            self.average_path_length(),
+            self.escaped,
+            self.absorbed,
+            self.max_depth_terminations,
+            self.walk_terminations,
+        )
+        .expect("Writing render statistics");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+    use std::thread;
+
+    use super::*;
+    use crate::adaptive::AdaptiveSampling;
+    use crate::camera::Camera;
+    use crate::colour::Colour;
+    use crate::hittable::HittableList;
+    use crate::material::Lambertian;
+    use crate::sphere::Sphere;
+    use crate::vec3::Point3;
+
+    fn render_hit_calls(count_hits: bool, samples_per_pass: Option<i32>) -> [u64; 2] {
+        let mut world = HittableList::new();
+        for x in [-1.0, 1.0] {
+            world.add(Box::new(Sphere::new(
+                Point3::new(x, 0.0, -1.0),
+                0.8,
+                Rc::new(Lambertian::new(Colour::new(0.5, 0.5, 0.5))),
+            )));
+        }
+        let mut cam = Camera::new();
+        cam.set_image_width(16);
+        cam.set_adaptive_sampling(Some(AdaptiveSampling {
+            min_samples: 8,
+            max_samples: 8,
+            threshold: 0.0,
+        }));
+        cam.set_progressive(samples_per_pass);
+        cam.set_count_hits(count_hits);
+        cam.render(&world, &[]).stats.hit_calls
+    }
+
+    #[test]
+    fn hit_calls_add_up_over_passes_and_stay_on_their_thread() {
+        let counts = render_hit_calls(true, None);
+        assert!(counts.iter().all(|&n| n > 0));
+        // Every sphere test comes from the list
+        assert_eq!(
+            counts[Primitive::Sphere as usize],
+            2 * counts[Primitive::List as usize]
+        );
+        assert_eq!(render_hit_calls(true, Some(2)), counts);
+
+        let threads: Vec<_> = (0..2)
+            .map(|_| thread::spawn(|| render_hit_calls(true, Some(4))))
+            .collect();
+        for thread in threads {
+            assert_eq!(thread.join().unwrap(), counts);
+        }
+    }
+
+    #[test]
+    fn nothing_is_counted_while_counting_is_off() {
+        assert_eq!(render_hit_calls(false, None), [0, 0]);
+
+        set_counting(false);
+        let before = hit_calls();
+        count_hit(Primitive::Sphere);
+        assert_eq!(hit_calls(), before);
+
+        // Switching it on for another thread leaves this one alone
+        thread::spawn(|| set_counting(true)).join().unwrap();
+        count_hit(Primitive::List);
+        assert_eq!(hit_calls(), before);
+    }
+}
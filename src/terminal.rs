@@ -0,0 +1,144 @@
+use std::io::Write;
+
+use crate::colour;
+use crate::film::Film;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ColourMode {
+    TrueColour,
+    Ansi256,
+}
+
+impl ColourMode {
+    // Terminals advertising 24-bit support set COLORTERM
+    pub fn detect() -> ColourMode {
+        match std::env::var("COLORTERM").as_deref() {
+            Ok("truecolor") | Ok("24bit") => ColourMode::TrueColour,
+            _ => ColourMode::Ansi256,
+        }
+    }
+
+    fn foreground(self, [r, g, b]: [u8; 3]) -> String {
+        match self {
+            ColourMode::TrueColour => format!("\x1b[38;2;{};{};{}m", r, g, b),
+            ColourMode::Ansi256 => format!("\x1b[38;5;{}m", ansi256([r, g, b])),
+        }
+    }
+
+    fn background(self, [r, g, b]: [u8; 3]) -> String {
+        match self {
+            ColourMode::TrueColour => format!("\x1b[48;2;{};{};{}m", r, g, b),
+            ColourMode::Ansi256 => format!("\x1b[48;5;{}m", ansi256([r, g, b])),
+        }
+    }
+}
+
+// Draws the film over the top of the terminal, each character cell shows two
+// pixels: the upper half block in the foreground colour and the lower pixel
+// as the cell background
+pub fn draw(out: &mut impl Write, film: &Film, mode: ColourMode) {
+    let mut frame = String::from("\x1b[H");
+    for y in (0..film.height()).step_by(2) {
+        for x in 0..film.width() {
+            let top = colour::to_rgb8(film.pixel(x, y), 1);
+            let bottom = if y + 1 < film.height() {
+                colour::to_rgb8(film.pixel(x, y + 1), 1)
+            } else {
+                [0, 0, 0]
+            };
+            frame += &mode.foreground(top);
+            frame += &mode.background(bottom);
+            frame.push('▀');
+        }
+        frame += "\x1b[0m\n";
+    }
+    out.write_all(frame.as_bytes())
+        .expect("Writing terminal frame");
+    out.flush().expect("Writing terminal frame");
+}
+
+pub fn clear(out: &mut impl Write) {
+    write!(out, "\x1b[2J").expect("Clearing terminal");
+}
+
+// Channel values of the 6x6x6 colour cube at palette entries 16-231, which
+// are not evenly spaced
+const CUBE_LEVELS: [i32; 6] = [0, 95, 135, 175, 215, 255];
+
+// Nearest entry of the 6x6x6 colour cube or the 24 step grey ramp, whose
+// entries 232-255 are the greys 8, 18, ..., 238
+fn ansi256([r, g, b]: [u8; 3]) -> u8 {
+    let rgb = [r as i32, g as i32, b as i32];
+    let error = |value: [i32; 3]| {
+        rgb.iter()
+            .zip(value)
+            .map(|(v, w)| (v - w).pow(2))
+            .sum::<i32>()
+    };
+
+    // Squared distance separates by channel, so the nearest level of each
+    // channel gives the nearest cube entry
+    let nearest_level = |v: i32| {
+        (0..CUBE_LEVELS.len())
+            .min_by_key(|&l| (v - CUBE_LEVELS[l]).abs())
+            .unwrap()
+    };
+    let levels = rgb.map(nearest_level);
+    let cube_error = error(levels.map(|l| CUBE_LEVELS[l]));
+
+    let grey_level = (0..24).min_by_key(|&l| error([8 + 10 * l; 3])).unwrap();
+    let grey_error = error([8 + 10 * grey_level; 3]);
+
+    if grey_error < cube_error {
+        232 + grey_level as u8
+    } else {
+        (16 + 36 * levels[0] + 6 * levels[1] + levels[2]) as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The colour xterm shows for a palette entry above 15
+    fn palette(index: u8) -> [u8; 3] {
+        if index >= 232 {
+            [8 + 10 * (index - 232); 3]
+        } else {
+            let i = index as usize - 16;
+            [i / 36, i / 6 % 6, i % 6].map(|l| CUBE_LEVELS[l] as u8)
+        }
+    }
+
+    #[test]
+    fn palette_entries_map_to_themselves() {
+        for index in 16..=255 {
+            assert_eq!(ansi256(palette(index)), index);
+        }
+    }
+
+    #[test]
+    fn picks_nearest_entry() {
+        let distance = |a: [u8; 3], b: [u8; 3]| {
+            a.iter()
+                .zip(b)
+                .map(|(&x, y)| (x as i32 - y as i32).pow(2))
+                .sum::<i32>()
+        };
+        for rgb in [
+            [40, 40, 40],
+            [40, 0, 0],
+            [120, 60, 200],
+            [250, 250, 245],
+            [5, 90, 160],
+        ] {
+            let best = (16..=255)
+                .map(|index| distance(rgb, palette(index)))
+                .min()
+                .unwrap();
+            assert_eq!(distance(rgb, palette(ansi256(rgb))), best);
+        }
+        // Rounding 40 / 255 * 5 would have picked 95 for the red channel
+        assert_eq!(ansi256([40, 200, 0]), 16 + 6 * 4);
+    }
+}
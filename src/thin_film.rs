@@ -0,0 +1,175 @@
+use std::ops::{Add, Div, Mul, Sub};
+
+use crate::colour::Colour;
+use crate::common::PI;
+use crate::spectrum;
+
+// Transparent coating a fraction of a wavelength thick, as on soap bubbles,
+// oil slicks or anodised metal. Light reflected off its top and bottom
+// interfaces interferes, so the reflectance depends on the wavelength and
+// shifts with the viewing angle.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ThinFilm {
+    // In nanometres
+    pub thickness: f64,
+    pub refractive_index: f64,
+}
+
+impl ThinFilm {
+    pub fn new(thickness: f64, refractive_index: f64) -> ThinFilm {
+        ThinFilm {
+            thickness,
+            refractive_index,
+        }
+    }
+
+    // Reflectance at one wavelength of the film between a dielectric of
+    // index `exterior`, on the side light arrives from, and a base of complex
+    // index eta + ik. The Airy sum adds up every path bouncing back and forth
+    // inside the film.
+    pub fn reflectance(&self, cos_theta: f64, exterior: f64, base: (f64, f64), lambda: f64) -> f64 {
+        let cos_theta = cos_theta.clamp(0.0, 1.0);
+        let sin2 = 1.0 - cos_theta * cos_theta;
+        let n0 = Complex::real(exterior);
+        let n1 = Complex::real(self.refractive_index);
+        let n2 = Complex::new(base.0, base.1);
+        // Snell's law with complex indices gives complex cosines past the
+        // critical angle and inside absorbing media
+        let cosine = |n: Complex| {
+            let sin = n0 * Complex::real(sin2.sqrt()) / n;
+            (Complex::real(1.0) - sin * sin).sqrt()
+        };
+        let (cos0, cos1, cos2) = (Complex::real(cos_theta), cosine(n1), cosine(n2));
+
+        // Phase difference of one round trip through the film
+        let delta = Complex::real(4.0 * PI * self.thickness / lambda) * n1 * cos1;
+        let phase = (Complex::new(0.0, 1.0) * delta).exp();
+
+        let airy = |r01: Complex, r12: Complex| {
+            let r = (r01 + r12 * phase) / (Complex::real(1.0) + r01 * r12 * phase);
+            r.norm_sqr()
+        };
+        let s = |na: Complex, ca: Complex, nb: Complex, cb: Complex| {
+            (na * ca - nb * cb) / (na * ca + nb * cb)
+        };
+        let p = |na: Complex, ca: Complex, nb: Complex, cb: Complex| {
+            (nb * ca - na * cb) / (nb * ca + na * cb)
+        };
+        let rs = airy(s(n0, cos0, n1, cos1), s(n1, cos1, n2, cos2));
+        let rp = airy(p(n0, cos0, n1, cos1), p(n1, cos1, n2, cos2));
+        (0.5 * (rs + rp)).clamp(0.0, 1.0)
+    }
+
+    // Reflectance over the visible spectrum, for RGB rendering
+    pub fn reflectance_rgb(&self, cos_theta: f64, exterior: f64, base: (Colour, Colour)) -> Colour {
+        spectrum::reflectance_to_rgb(|lambda| {
+            let eta = spectrum::rgb_at_wavelength(base.0, lambda);
+            let k = spectrum::rgb_at_wavelength(base.1, lambda);
+            self.reflectance(cos_theta, exterior, (eta, k), lambda)
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Complex {
+    re: f64,
+    im: f64,
+}
+
+impl Complex {
+    fn new(re: f64, im: f64) -> Complex {
+        Complex { re, im }
+    }
+
+    fn real(re: f64) -> Complex {
+        Complex::new(re, 0.0)
+    }
+
+    fn norm_sqr(self) -> f64 {
+        self.re * self.re + self.im * self.im
+    }
+
+    // Principal root, with a non-negative real part
+    fn sqrt(self) -> Complex {
+        let r = self.norm_sqr().sqrt();
+        let re = (0.5 * (r + self.re)).max(0.0).sqrt();
+        let im = (0.5 * (r - self.re)).max(0.0).sqrt();
+        Complex::new(re, if self.im < 0.0 { -im } else { im })
+    }
+
+    fn exp(self) -> Complex {
+        let magnitude = self.re.exp();
+        Complex::new(magnitude * self.im.cos(), magnitude * self.im.sin())
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+
+    fn add(self, rhs: Complex) -> Complex {
+        Complex::new(self.re + rhs.re, self.im + rhs.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+
+    fn sub(self, rhs: Complex) -> Complex {
+        Complex::new(self.re - rhs.re, self.im - rhs.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+
+    fn mul(self, rhs: Complex) -> Complex {
+        Complex::new(
+            self.re * rhs.re - self.im * rhs.im,
+            self.re * rhs.im + self.im * rhs.re,
+        )
+    }
+}
+
+impl Div for Complex {
+    type Output = Complex;
+
+    fn div(self, rhs: Complex) -> Complex {
+        let denominator = rhs.norm_sqr();
+        Complex::new(
+            (self.re * rhs.re + self.im * rhs.im) / denominator,
+            (self.im * rhs.re - self.re * rhs.im) / denominator,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::microfacet::fresnel_dielectric;
+
+    #[test]
+    fn quarter_wave_coating() {
+        // MgF2 a quarter wave thick on crown glass at 550nm: the two
+        // reflections cancel as far as the indices allow, leaving
+        // ((n2 - n1^2) / (n2 + n1^2))^2
+        let (n1, n2, lambda) = (1.38, 1.52, 550.0);
+        let film = ThinFilm::new(lambda / (4.0 * n1), n1);
+        let expected = ((n2 - n1 * n1) / (n2 + n1 * n1)).powi(2);
+        let r = film.reflectance(1.0, 1.0, (n2, 0.0), lambda);
+        assert!((r - expected).abs() < 1e-9, "{} != {}", r, expected);
+        assert!((expected - 0.012601).abs() < 1e-6);
+        // A half wave layer is absent at that wavelength
+        let film = ThinFilm::new(lambda / (2.0 * n1), n1);
+        let bare = fresnel_dielectric(1.0, n2);
+        assert!((film.reflectance(1.0, 1.0, (n2, 0.0), lambda) - bare).abs() < 1e-9);
+    }
+
+    #[test]
+    fn zero_thickness_is_bare_interface() {
+        let film = ThinFilm::new(0.0, 1.38);
+        for cos_theta in [1.0, 0.7, 0.3, 0.05] {
+            let r = film.reflectance(cos_theta, 1.0, (1.5, 0.0), 500.0);
+            assert!((r - fresnel_dielectric(cos_theta, 1.5)).abs() < 1e-9);
+        }
+    }
+}
@@ -1,8 +1,8 @@
-use crate::common::random_double_range;
+use crate::common::{random_double, random_double_range, PI};
 use std::fmt::{Display, Formatter};
 use std::ops::{Add, AddAssign, Div, Mul, Neg, Sub};
 
-#[derive(Copy, Clone, Default)]
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
 pub struct Vec3 {
     f: [f64; 3],
 }
@@ -139,16 +139,11 @@ impl Div<f64> for Vec3 {
 }
 
 pub fn random_in_unit_sphere() -> Vec3 {
-    loop {
-        let v = random_range(-1.0, 1.0);
-        if v.length_squared() < 1.0 {
-            return v;
-        }
-    }
+    sample_in_unit_sphere((random_double(), random_double()), random_double())
 }
 
 pub fn random_unit_vector() -> Vec3 {
-    unit_vector(random_in_unit_sphere())
+    sample_unit_vector((random_double(), random_double()))
 }
 
 pub fn random_on_hemisphere(normal: &Vec3) -> Vec3 {
@@ -170,25 +165,49 @@ pub fn reflect(v: Vec3, n: Vec3) -> Vec3 {
     v - 2.0 * v.dot(&n) * n
 }
 
-pub fn refract(uv: Vec3, n: Vec3, relative_refractive_index: f64) -> Vec3 {
+// None on total internal reflection
+pub fn refract(uv: Vec3, n: Vec3, relative_refractive_index: f64) -> Option<Vec3> {
     let cos_theta = -uv.dot(&n).min(1.0);
     let r_perpendicular = relative_refractive_index * (uv + n * cos_theta);
-    let r_parallel = -f64::sqrt(f64::abs(1.0 - r_perpendicular.length_squared())) * n;
+    let cos_squared = 1.0 - r_perpendicular.length_squared();
+    if cos_squared < 0.0 {
+        return None;
+    }
+    let r_parallel = -f64::sqrt(cos_squared) * n;
 
-    r_perpendicular + r_parallel
+    Some(r_perpendicular + r_parallel)
 }
 
 pub fn random_in_unit_disk() -> Vec3 {
-    loop {
-        let v = Vec3::new(
-            random_double_range(-1.0, 1.0),
-            random_double_range(-1.0, 1.0),
-            0.0,
-        );
-        if v.length_squared() < 1.0 {
-            return v;
-        }
+    sample_unit_disk((random_double(), random_double()))
+}
+
+// Shirley-Chiu concentric mapping from the unit square to the unit disk
+pub fn sample_unit_disk(u: (f64, f64)) -> Vec3 {
+    let (ox, oy) = (2.0 * u.0 - 1.0, 2.0 * u.1 - 1.0);
+    if ox == 0.0 && oy == 0.0 {
+        return Vec3::default();
     }
+
+    let (r, theta) = if ox.abs() > oy.abs() {
+        (ox, (PI / 4.0) * (oy / ox))
+    } else {
+        (oy, PI / 2.0 - (PI / 4.0) * (ox / oy))
+    };
+    Vec3::new(r * theta.cos(), r * theta.sin(), 0.0)
+}
+
+// Uniform direction on the unit sphere
+pub fn sample_unit_vector(u: (f64, f64)) -> Vec3 {
+    let z = 1.0 - 2.0 * u.0;
+    let r = f64::sqrt(f64::max(0.0, 1.0 - z * z));
+    let phi = 2.0 * PI * u.1;
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+// Uniform point inside the unit sphere, the cube root keeps density constant with radius
+pub fn sample_in_unit_sphere(u: (f64, f64), radius: f64) -> Vec3 {
+    radius.cbrt() * sample_unit_vector(u)
 }
 
 pub fn random_range(min: f64, max: f64) -> Vec3 {